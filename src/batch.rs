@@ -0,0 +1,100 @@
+use eliza::{Eliza, Trace};
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+
+/// The response to a message, as written by `eliza batch`.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    input: &'a str,
+    response: &'a str,
+    is_farewell: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<&'a Trace>,
+}
+
+/// Answers each line of the input, skipping empty ones, and writes one JSON
+/// object per line. Unlike a conversation, it goes on after a farewell.
+pub fn run<R: BufRead, W: Write>(
+    eliza: &Eliza,
+    input: R,
+    mut output: W,
+    trace: bool,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let answer = eliza.trace(line);
+        let record = Record {
+            input: line,
+            response: &answer.response.message,
+            is_farewell: answer.response.is_farewell,
+            trace: if trace { Some(&answer) } else { None },
+        };
+
+        serde_json::to_writer(&mut output, &record)?;
+        writeln!(output)?;
+    }
+
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eliza::Rules;
+    use serde_json::Value;
+
+    fn eliza() -> Eliza {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut eliza = Eliza::new(rules);
+        eliza.seed(1);
+        eliza
+    }
+
+    fn records(input: &str, trace: bool) -> Vec<Value> {
+        let mut output = Vec::new();
+        run(&eliza(), input.as_bytes(), &mut output, trace).unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_run() {
+        let records = records("hello\n\n  bye  \nhello\n", false);
+
+        assert_eq!(3, records.len());
+        assert_eq!("hello", records[0]["input"]);
+        assert_eq!(false, records[0]["is_farewell"]);
+        assert!(records[0].get("trace").is_none());
+
+        assert_eq!("bye", records[1]["input"]);
+        assert_eq!(true, records[1]["is_farewell"]);
+        assert_eq!(
+            "Goodbye.  Thank you for talking to me.",
+            records[1]["response"]
+        );
+
+        // Going on after the farewell.
+        assert_eq!("hello", records[2]["input"]);
+    }
+
+    #[test]
+    fn test_run_trace() {
+        let records = records("hello\n", true);
+        let trace = &records[0]["trace"];
+
+        assert_eq!("hello", trace["keyword"]);
+        assert_eq!("*", trace["pattern"]);
+        assert_eq!(false, trace["quit"]);
+        assert!(trace.get("response").is_none());
+    }
+}
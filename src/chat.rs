@@ -0,0 +1,337 @@
+use crate::eliza::{Eliza, Trace};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sent when a conversation has no script to answer it.
+pub const NO_RULES: &str = "Error: no rules defined";
+
+/// What happens in a conversation, once translated from a chat platform.
+/// Conversations are named by the frontend, such as a channel id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A conversation starts, or starts over, and should be greeted.
+    Open(String),
+    /// A message of the user.
+    Message { conversation: String, text: String },
+    /// The user left without saying goodbye.
+    Close(String),
+}
+
+/// What to send back to a conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub conversation: String,
+    pub text: String,
+    /// Whether the conversation is over, see `Response::is_farewell`.
+    pub is_farewell: bool,
+    /// How the reply was chosen, for the replies to messages.
+    pub trace: Option<Trace>,
+}
+
+impl Reply {
+    /// Tells a conversation which has no script that it cannot be answered.
+    pub fn no_rules(conversation: &str) -> Self {
+        Reply {
+            conversation: conversation.to_string(),
+            text: NO_RULES.to_string(),
+            is_farewell: false,
+            trace: None,
+        }
+    }
+}
+
+/// Translates the messages of a chat platform, so that `run` can answer
+/// them. Frontends driven by their own event loop, such as discord or HTTP,
+/// build the events themselves and call `Sessions::handle` instead.
+pub trait Transport {
+    /// Waits for the next event, or returns `None` when there are no more.
+    fn receive(&mut self) -> io::Result<Option<Event>>;
+
+    fn send(&mut self, reply: &Reply) -> io::Result<()>;
+}
+
+/// The conversations in progress: greets them when they open, cleans up the
+/// messages, and ends them on farewells, or after being idle for too long if
+/// built with `Sessions::with_idle_timeout`.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    active: Mutex<Active>,
+    idle_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Active {
+    /// When each conversation was last active.
+    conversations: HashMap<String, Instant>,
+    /// When the idle conversations were last forgotten.
+    pruned: Option<Instant>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Sessions::default()
+    }
+
+    /// Forgets the conversations without any event for `idle_timeout`, so
+    /// that they do not pile up when users leave without saying goodbye.
+    pub fn with_idle_timeout(idle_timeout: Duration) -> Self {
+        Sessions {
+            active: Mutex::default(),
+            idle_timeout: Some(idle_timeout),
+        }
+    }
+
+    /// Whether a conversation was opened, or has messages, and has not ended.
+    pub fn is_active(&self, conversation: &str) -> bool {
+        self.is_active_at(conversation, Instant::now())
+    }
+
+    fn is_active_at(&self, conversation: &str, now: Instant) -> bool {
+        let active = self.active.lock().unwrap();
+
+        match active.conversations.get(conversation) {
+            Some(last) => !self.is_idle(*last, now),
+            None => false,
+        }
+    }
+
+    /// Answers an event with a script. Empty messages and `Close` events get
+    /// no reply. A message to a conversation which was never opened, or has
+    /// ended, is answered and makes it active again, without a greeting, as
+    /// some platforms have no way to open conversations.
+    ///
+    /// Frontends remove their own syntax, such as the mentions and command
+    /// prefix of discord, before building the event.
+    pub fn handle(&self, eliza: &Eliza, event: Event) -> Option<Reply> {
+        self.handle_at(eliza, event, Instant::now())
+    }
+
+    fn handle_at(&self, eliza: &Eliza, event: Event, now: Instant) -> Option<Reply> {
+        let mut active = self.active.lock().unwrap();
+        self.prune(&mut active, now);
+
+        match event {
+            Event::Open(conversation) => {
+                active.conversations.insert(conversation.clone(), now);
+
+                Some(Reply {
+                    conversation,
+                    text: eliza.greeting().to_string(),
+                    is_farewell: false,
+                    trace: None,
+                })
+            }
+            Event::Message { conversation, text } => {
+                let text = clean(&text);
+
+                if text.is_empty() {
+                    return None;
+                }
+
+                let trace = eliza.trace(&text);
+
+                if trace.response.is_farewell {
+                    active.conversations.remove(&conversation);
+                } else {
+                    active.conversations.insert(conversation.clone(), now);
+                }
+
+                Some(Reply {
+                    conversation,
+                    text: trace.response.message.clone(),
+                    is_farewell: trace.response.is_farewell,
+                    trace: Some(trace),
+                })
+            }
+            Event::Close(conversation) => {
+                active.conversations.remove(&conversation);
+                None
+            }
+        }
+    }
+
+    fn is_idle(&self, last: Instant, now: Instant) -> bool {
+        match self.idle_timeout {
+            Some(timeout) => now.saturating_duration_since(last) >= timeout,
+            None => false,
+        }
+    }
+
+    /// Forgets the idle conversations, at most once per timeout as it goes
+    /// through all of them. They are already inactive for `is_active`.
+    fn prune(&self, active: &mut Active, now: Instant) {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        let pruned = *active.pruned.get_or_insert(now);
+
+        if now.saturating_duration_since(pruned) >= timeout {
+            active
+                .conversations
+                .retain(|_, last| now.saturating_duration_since(*last) < timeout);
+            active.pruned = Some(now);
+        }
+    }
+}
+
+/// Trims a message and collapses its whitespace, which would otherwise make
+/// patterns miss. Platform syntax is left to the frontends.
+pub fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Answers the events of a transport with a script, until it has no more.
+pub fn run<T: Transport>(eliza: &Eliza, sessions: &Sessions, transport: &mut T) -> io::Result<()> {
+    while let Some(event) = transport.receive()? {
+        if let Some(reply) = sessions.handle(eliza, event) {
+            transport.send(&reply)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A transport replaying a list of events, and keeping the replies, to test
+/// conversations without a chat platform.
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    pub events: VecDeque<Event>,
+    pub replies: Vec<Reply>,
+}
+
+impl MemoryTransport {
+    pub fn new<I: IntoIterator<Item = Event>>(events: I) -> Self {
+        MemoryTransport {
+            events: events.into_iter().collect(),
+            replies: Vec::new(),
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn receive(&mut self) -> io::Result<Option<Event>> {
+        Ok(self.events.pop_front())
+    }
+
+    fn send(&mut self, reply: &Reply) -> io::Result<()> {
+        self.replies.push(reply.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+
+    fn eliza() -> Eliza {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut eliza = Eliza::new(rules);
+        eliza.cycle();
+        eliza
+    }
+
+    fn message(conversation: &str, text: &str) -> Event {
+        Event::Message {
+            conversation: conversation.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_clean() {
+        assert_eq!("i am sad", clean("  i am \t sad \n"));
+        assert_eq!("", clean("   "));
+    }
+
+    #[test]
+    fn test_run() {
+        let eliza = eliza();
+        let sessions = Sessions::new();
+        let mut transport = MemoryTransport::new(vec![
+            Event::Open("a".to_string()),
+            message("a", "sorry"),
+            message("b", "   "),
+            message("b", "  sorry  "),
+            message("a", "bye"),
+        ]);
+
+        run(&eliza, &sessions, &mut transport).unwrap();
+
+        let replies: Vec<(&str, &str, bool)> = transport
+            .replies
+            .iter()
+            .map(|r| (r.conversation.as_str(), r.text.as_str(), r.is_farewell))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("a", "How do you do.  Please tell me your problem.", false),
+                ("a", "Please don't apologise.", false),
+                ("b", "Apologies are not necessary.", false),
+                ("a", "Goodbye.  Thank you for talking to me.", true),
+            ],
+            replies
+        );
+
+        // The farewell ended the first conversation only.
+        assert!(!sessions.is_active("a"));
+        assert!(sessions.is_active("b"));
+    }
+
+    #[test]
+    fn test_close() {
+        let eliza = eliza();
+        let sessions = Sessions::new();
+
+        assert!(sessions
+            .handle(&eliza, Event::Open("a".to_string()))
+            .is_some());
+        assert!(sessions.is_active("a"));
+
+        assert_eq!(None, sessions.handle(&eliza, Event::Close("a".to_string())));
+        assert!(!sessions.is_active("a"));
+
+        // A message after closing is answered, and makes it active again.
+        let reply = sessions.handle(&eliza, message("a", "sorry")).unwrap();
+        assert!(!reply.is_farewell);
+        assert!(reply.trace.is_some());
+        assert!(sessions.is_active("a"));
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let eliza = eliza();
+        let sessions = Sessions::with_idle_timeout(Duration::from_secs(60));
+        let now = Instant::now();
+        let later = |seconds| now + Duration::from_secs(seconds);
+
+        sessions.handle_at(&eliza, Event::Open("a".to_string()), now);
+        sessions.handle_at(&eliza, Event::Open("b".to_string()), later(30));
+        assert!(sessions.is_active_at("a", later(59)));
+        assert!(!sessions.is_active_at("a", later(60)));
+        assert!(sessions.is_active_at("b", later(60)));
+
+        // A message keeps the conversation going.
+        sessions.handle_at(&eliza, message("b", "sorry"), later(60));
+        assert!(sessions.is_active_at("b", later(100)));
+
+        sessions.handle_at(&eliza, message("b", "sorry"), later(120));
+        let active = sessions.active.lock().unwrap();
+        assert_eq!(vec!["b"], active.conversations.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_no_idle_timeout() {
+        let eliza = eliza();
+        let sessions = Sessions::new();
+        let now = Instant::now();
+
+        sessions.handle_at(&eliza, Event::Open("a".to_string()), now);
+        assert!(sessions.is_active_at("a", now + Duration::from_secs(86_400)));
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// A (toy) implementation of Eliza, in the terminal or as a discord bot.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "eliza")]
+pub struct Opt {
+    /// Reads the environment variables from this file instead of `.env`.
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+// How scripts answer, for the commands talking with them. Not a doc comment,
+// which would replace the help of these commands.
+#[derive(Debug, StructOpt)]
+pub struct ScriptOptions {
+    /// Seeds the choice of responses, to make them reproducible.
+    #[structopt(long)]
+    pub seed: Option<u64>,
+    /// Prints how each response was chosen.
+    #[structopt(long)]
+    pub trace: bool,
+    /// The script to use when several are loaded, instead of
+    /// `ELIZA_DEFAULT_SCRIPT`.
+    #[structopt(long)]
+    pub script: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Chats with Eliza in the terminal.
+    #[structopt(alias = "cli")]
+    Chat {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Runs the discord bot.
+    #[cfg(feature = "discord")]
+    Discord {
+        /// Where the rules loaded in discord are stored: a directory, or a
+        /// database with `ELIZA_STORE=sqlite`.
+        #[structopt(parse(from_os_str))]
+        storage: PathBuf,
+    },
+    /// Checks rule files, and fails if one of them is invalid.
+    Check {
+        /// Rule files, or directories of rule files.
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Upgrades a rule file to the current version.
+    Convert {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Where to write the upgraded rules, instead of the standard output.
+        #[structopt(parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Shows how Eliza responds to a message.
+    Explain {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        message: String,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Replays a conversation, one message per line.
+    Replay {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        /// The messages, instead of the standard input.
+        #[structopt(parse(from_os_str))]
+        input: Option<PathBuf>,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Answers messages one per line, and writes the responses as JSON lines.
+    Batch {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        /// The messages, instead of the standard input.
+        #[structopt(parse(from_os_str))]
+        input: Option<PathBuf>,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Counts the rules used to answer a corpus, one message per line.
+    Coverage {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        /// The messages, instead of the standard input.
+        #[structopt(parse(from_os_str))]
+        input: Option<PathBuf>,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Plays transcripts, and fails if a reply differs from them.
+    Test {
+        /// Transcript files, or directories of `.transcript` files.
+        #[structopt(parse(from_os_str), default_value = "tests/transcripts")]
+        paths: Vec<PathBuf>,
+    },
+    /// Answers messages with a JSON API over HTTP.
+    #[cfg(feature = "http")]
+    Serve {
+        /// A rule file, or a directory of rule files.
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+        /// The address to listen on.
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        address: String,
+        #[structopt(flatten)]
+        options: ScriptOptions,
+    },
+    /// Writes the JSON Schema of rule files.
+    Schema {
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let opt = Opt::from_iter(&[
+            "eliza",
+            "chat",
+            "rules",
+            "--seed",
+            "42",
+            "--trace",
+            "--config",
+            "eliza.env",
+        ]);
+
+        assert_eq!(Some(PathBuf::from("eliza.env")), opt.config);
+
+        match opt.command {
+            Command::Chat { rules, options } => {
+                assert_eq!(PathBuf::from("rules"), rules);
+                assert_eq!(Some(42), options.seed);
+                assert!(options.trace);
+                assert_eq!(None, options.script);
+            }
+            other => panic!("expected chat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_alias() {
+        let opt = Opt::from_iter(&["eliza", "cli", "rules/therapist.json"]);
+        assert!(matches!(opt.command, Command::Chat { .. }));
+    }
+
+    #[test]
+    fn test_bad_input() {
+        assert!(Opt::from_iter_safe(&["eliza"]).is_err());
+        assert!(Opt::from_iter_safe(&["eliza", "talk", "rules"]).is_err());
+        assert!(Opt::from_iter_safe(&["eliza", "check"]).is_err());
+        assert!(Opt::from_iter_safe(&["eliza", "chat", "rules", "--seed", "x"]).is_err());
+    }
+}
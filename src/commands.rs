@@ -0,0 +1,178 @@
+use std::env;
+
+/// A command given to the bot, e.g. `!load_rules channel <url>`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Command {
+    /// The name of the command, without the prefix.
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// A command understood by the bot, as listed by `!help`.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: [CommandInfo; 5] = [
+    CommandInfo {
+        name: "help",
+        usage: "",
+        description: "List the commands.",
+    },
+    CommandInfo {
+        name: "ping",
+        usage: "",
+        description: "Check that the bot is alive.",
+    },
+    CommandInfo {
+        name: "load_rules",
+        usage: "[channel|global] <url>",
+        description: "Load rules from a URL or an attached file.",
+    },
+    CommandInfo {
+        name: "rules",
+        usage: "[channel|global] history|rollback <n>|diff <n>",
+        description: "List, restore or compare the previously loaded rules.",
+    },
+    CommandInfo {
+        name: "use",
+        usage: "[name|default]",
+        description: "List the scripts, or select the one used in this channel.",
+    },
+];
+
+/// Recognizes the commands of the messages sent to the bot.
+#[derive(Debug)]
+pub struct CommandParser {
+    /// What commands start with, `!` by default.
+    pub prefix: String,
+}
+
+impl Default for CommandParser {
+    fn default() -> Self {
+        CommandParser {
+            prefix: "!".to_string(),
+        }
+    }
+}
+
+impl CommandParser {
+    /// Reads the prefix from `ELIZA_COMMAND_PREFIX`, if set.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("ELIZA_COMMAND_PREFIX") {
+            Ok(prefix) if prefix.trim().is_empty() => {
+                Err("ELIZA_COMMAND_PREFIX cannot be empty".to_string())
+            }
+            Ok(prefix) => Ok(CommandParser {
+                prefix: prefix.trim().to_string(),
+            }),
+            Err(_) => Ok(CommandParser::default()),
+        }
+    }
+
+    /// Returns the command a message starts with, if any. Its mentions of
+    /// the bot should be removed first, see `strip_mentions`.
+    pub fn parse(&self, text: &str) -> Option<Command> {
+        let rest = text.trim().strip_prefix(&self.prefix)?;
+
+        // `! ping` is not a command.
+        if rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let mut words = rest.split_whitespace();
+        let name = words.next()?.to_string();
+
+        Some(Command {
+            name,
+            args: words.map(str::to_string).collect(),
+        })
+    }
+
+    /// Returns how to use a command, e.g. `!use [name|default]`.
+    pub fn usage(&self, name: &str) -> String {
+        match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) if !command.usage.is_empty() => {
+                format!("{}{} {}", self.prefix, command.name, command.usage)
+            }
+            _ => format!("{}{}", self.prefix, name),
+        }
+    }
+
+    /// Lists the commands.
+    pub fn help(&self) -> String {
+        let lines: Vec<String> = COMMANDS
+            .iter()
+            .map(|command| format!("`{}`: {}", self.usage(command.name), command.description))
+            .collect();
+
+        lines.join("\n")
+    }
+}
+
+/// Removes the mentions of a user from a message, in both the `<@id>` and
+/// the `<@!id>` (nickname) forms.
+pub fn strip_mentions(content: &str, user_id: u64) -> String {
+    content
+        .replace(&format!("<@{}>", user_id), "")
+        .replace(&format!("<@!{}>", user_id), "")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str, args: &[&str]) -> Option<Command> {
+        Some(Command {
+            name: name.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let parser = CommandParser::default();
+
+        assert_eq!(command("ping", &[]), parser.parse("!ping"));
+        assert_eq!(
+            command("load_rules", &["channel", "https://example.com/rules.json"]),
+            parser.parse("  !load_rules channel   https://example.com/rules.json ")
+        );
+
+        assert_eq!(None, parser.parse("I said !ping"));
+        assert_eq!(None, parser.parse("! ping"));
+        assert_eq!(None, parser.parse("!"));
+        assert_eq!(None, parser.parse("ping"));
+    }
+
+    #[test]
+    fn test_parse_custom_prefix() {
+        let parser = CommandParser {
+            prefix: "eliza:".to_string(),
+        };
+
+        assert_eq!(command("use", &["tutor"]), parser.parse("eliza:use tutor"));
+        assert_eq!(None, parser.parse("!use tutor"));
+        assert!(parser.help().contains("`eliza:use [name|default]`"));
+    }
+
+    #[test]
+    fn test_strip_mentions() {
+        assert_eq!("!ping", strip_mentions("<@42> !ping", 42));
+        assert_eq!("!ping", strip_mentions("<@!42> !ping", 42));
+        assert_eq!("hello <@43>", strip_mentions("<@!42> hello <@43>", 42));
+    }
+
+    #[test]
+    fn test_help_lists_every_command() {
+        let help = CommandParser::default().help();
+
+        for command in COMMANDS.iter() {
+            assert!(help.contains(&format!("`!{}", command.name)));
+        }
+    }
+}
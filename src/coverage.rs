@@ -0,0 +1,293 @@
+use crate::eliza::Trace;
+use crate::rules::Rules;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of unmatched words listed as candidates for new keywords.
+const CANDIDATES: usize = 10;
+
+/// How often each keyword, decomposition and template answered a corpus.
+#[derive(Debug)]
+pub struct Coverage {
+    /// In the order of the rules, without `xnone` which is counted in
+    /// `unmatched`.
+    pub keywords: Vec<KeywordCoverage>,
+    pub inputs: usize,
+    pub quits: usize,
+    /// The inputs no pattern matched, answered by `xnone`.
+    pub unmatched: Vec<String>,
+    /// How often each word appears in `unmatched`, keywords excepted.
+    pub unmatched_words: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+pub struct KeywordCoverage {
+    pub word: String,
+    pub hits: usize,
+    pub decompositions: Vec<DecompositionCoverage>,
+}
+
+#[derive(Debug)]
+pub struct DecompositionCoverage {
+    pub pattern: String,
+    pub hits: usize,
+    /// Each template of `reasmb`, and how often it was used.
+    pub templates: Vec<(String, usize)>,
+}
+
+impl Coverage {
+    pub fn new(rules: &Rules) -> Self {
+        let keywords = rules
+            .keywords
+            .iter()
+            .filter(|k| k.word != "xnone")
+            .map(|k| KeywordCoverage {
+                word: k.word.clone(),
+                hits: 0,
+                decompositions: k
+                    .decomposition
+                    .iter()
+                    .map(|d| DecompositionCoverage {
+                        pattern: d.pattern.clone(),
+                        hits: 0,
+                        templates: d.reasmb.iter().map(|t| (t.clone(), 0)).collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Coverage {
+            keywords,
+            inputs: 0,
+            quits: 0,
+            unmatched: Vec::new(),
+            unmatched_words: HashMap::new(),
+        }
+    }
+
+    /// Counts the rules used to answer an input, see `Eliza::trace`.
+    pub fn record(&mut self, input: &str, trace: &Trace) {
+        self.inputs += 1;
+
+        if trace.quit {
+            self.quits += 1;
+            return;
+        }
+
+        let (word, pattern) = match (&trace.keyword, &trace.pattern) {
+            (Some(word), Some(pattern)) => (word, pattern),
+            _ => {
+                self.unmatched.push(input.to_string());
+
+                for word in trace.input.split_whitespace() {
+                    if !self.keywords.iter().any(|k| k.word == word) {
+                        *self.unmatched_words.entry(word.to_string()).or_insert(0) += 1;
+                    }
+                }
+
+                return;
+            }
+        };
+
+        let keyword = match self.keywords.iter_mut().find(|k| &k.word == word) {
+            Some(keyword) => keyword,
+            None => return,
+        };
+        keyword.hits += 1;
+
+        // The first one, as later ones with the same pattern are never used.
+        let decomposition = match keyword
+            .decompositions
+            .iter_mut()
+            .find(|d| &d.pattern == pattern)
+        {
+            Some(decomposition) => decomposition,
+            None => return,
+        };
+        decomposition.hits += 1;
+
+        if let Some(template) = &trace.template {
+            if let Some((_, hits)) = decomposition
+                .templates
+                .iter_mut()
+                .find(|(t, _)| t == template)
+            {
+                *hits += 1;
+            }
+        }
+    }
+
+    /// The rules which were never used, described like validation warnings.
+    pub fn never_hit(&self) -> Vec<String> {
+        let mut rules = Vec::new();
+
+        for keyword in &self.keywords {
+            if keyword.hits == 0 {
+                rules.push(format!("keyword '{}'", keyword.word));
+                continue;
+            }
+
+            for decomposition in &keyword.decompositions {
+                if decomposition.hits == 0 {
+                    rules.push(format!(
+                        "keyword '{}', pattern '{}'",
+                        keyword.word, decomposition.pattern
+                    ));
+                    continue;
+                }
+
+                for (template, hits) in &decomposition.templates {
+                    if *hits == 0 {
+                        rules.push(format!(
+                            "keyword '{}', pattern '{}', template '{}'",
+                            keyword.word, decomposition.pattern, template
+                        ));
+                    }
+                }
+            }
+        }
+
+        rules
+    }
+
+    /// The most common unmatched words, most common first, then in
+    /// alphabetical order.
+    pub fn candidates(&self) -> Vec<(&str, usize)> {
+        let mut words: Vec<(&str, usize)> = self
+            .unmatched_words
+            .iter()
+            .map(|(word, count)| (word.as_str(), *count))
+            .collect();
+
+        words.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        words.truncate(CANDIDATES);
+        words
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} inputs: {} quit, {} unmatched.",
+            self.inputs,
+            self.quits,
+            self.unmatched.len()
+        )?;
+
+        writeln!(f, "\nHits:")?;
+
+        for keyword in self.keywords.iter().filter(|k| k.hits > 0) {
+            writeln!(f, "{:>6}  {}", keyword.hits, keyword.word)?;
+
+            for decomposition in keyword.decompositions.iter().filter(|d| d.hits > 0) {
+                writeln!(f, "{:>6}    {}", decomposition.hits, decomposition.pattern)?;
+
+                for (template, hits) in decomposition.templates.iter().filter(|(_, h)| *h > 0) {
+                    writeln!(f, "{:>6}      {}", hits, template)?;
+                }
+            }
+        }
+
+        let never_hit = self.never_hit();
+
+        if !never_hit.is_empty() {
+            writeln!(f, "\nNever hit:")?;
+
+            for rule in never_hit {
+                writeln!(f, "  {}", rule)?;
+            }
+        }
+
+        if !self.unmatched.is_empty() {
+            writeln!(f, "\nUnmatched inputs:")?;
+
+            for input in &self.unmatched {
+                writeln!(f, "  {}", input)?;
+            }
+
+            writeln!(f, "\nCandidate keywords:")?;
+
+            for (word, count) in self.candidates() {
+                writeln!(f, "{:>6}  {}", count, word)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eliza::Eliza;
+
+    fn coverage(inputs: &[&str]) -> Coverage {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut coverage = Coverage::new(&rules);
+        let mut eliza = Eliza::new(rules);
+        eliza.cycle();
+
+        for input in inputs {
+            coverage.record(input, &eliza.trace(input));
+        }
+
+        coverage
+    }
+
+    fn keyword<'a>(coverage: &'a Coverage, word: &str) -> &'a KeywordCoverage {
+        coverage.keywords.iter().find(|k| k.word == word).unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let coverage = coverage(&[
+            "sorry",
+            "I am so sorry",
+            "my mother hates me",
+            "the weather today",
+            "hot weather",
+            "bye",
+        ]);
+
+        assert_eq!(6, coverage.inputs);
+        assert_eq!(1, coverage.quits);
+
+        let sorry = keyword(&coverage, "sorry");
+        assert_eq!(2, sorry.hits);
+        assert_eq!(2, sorry.decompositions[0].hits);
+
+        let templates: Vec<usize> = sorry.decompositions[0]
+            .templates
+            .iter()
+            .map(|(_, hits)| *hits)
+            .collect();
+        assert_eq!(vec![1, 1, 0], templates);
+
+        let my = keyword(&coverage, "my");
+        assert_eq!(1, my.hits);
+        assert_eq!(
+            vec![1, 0, 0],
+            my.decompositions.iter().map(|d| d.hits).collect::<Vec<_>>()
+        );
+
+        assert!(coverage.keywords.iter().all(|k| k.word != "xnone"));
+        assert_eq!(vec!["the weather today", "hot weather"], coverage.unmatched);
+        assert_eq!(
+            vec![("weather", 2), ("hot", 1), ("the", 1)],
+            coverage.candidates()[..3].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_never_hit() {
+        let coverage = coverage(&["sorry", "sorry", "sorry", "my mother hates me"]);
+        let never_hit = coverage.never_hit();
+
+        assert!(never_hit.contains(&"keyword 'computer'".to_string()));
+        assert!(never_hit.contains(&"keyword 'my', pattern '* my *'".to_string()));
+        assert!(!never_hit
+            .iter()
+            .any(|rule| rule.starts_with("keyword 'sorry'")));
+    }
+}
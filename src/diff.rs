@@ -0,0 +1,140 @@
+use crate::rules::Rules;
+use std::fmt;
+
+/// A difference between two rule sets.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(Item),
+    Removed(Item),
+    Changed(Item),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Item {
+    Initial,
+    Final,
+    Quit,
+    Pre,
+    Post,
+    Synonym(String),
+    Keyword(String),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(item) => write!(f, "+ {}", item),
+            Change::Removed(item) => write!(f, "- {}", item),
+            Change::Changed(item) => write!(f, "~ {}", item),
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Initial => write!(f, "greetings"),
+            Item::Final => write!(f, "farewells"),
+            Item::Quit => write!(f, "quit words"),
+            Item::Pre => write!(f, "pre replacements"),
+            Item::Post => write!(f, "post replacements"),
+            Item::Synonym(label) => write!(f, "synonym {}", label),
+            Item::Keyword(word) => write!(f, "keyword '{}'", word),
+        }
+    }
+}
+
+/// Lists what changed from `old` to `new`.
+pub fn diff(old: &Rules, new: &Rules) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let lists = vec![
+        (old.initial != new.initial, Item::Initial),
+        (old.final_ != new.final_, Item::Final),
+        (old.quit != new.quit, Item::Quit),
+        (old.pre != new.pre, Item::Pre),
+        (old.post != new.post, Item::Post),
+    ];
+
+    for (changed, item) in lists.into_iter() {
+        if changed {
+            changes.push(Change::Changed(item));
+        }
+    }
+
+    for synonym in &old.synonyms {
+        match new.synonyms.iter().find(|s| s.label == synonym.label) {
+            Some(s) if s != synonym => {
+                changes.push(Change::Changed(Item::Synonym(synonym.label.clone())))
+            }
+            Some(_) => {}
+            None => changes.push(Change::Removed(Item::Synonym(synonym.label.clone()))),
+        }
+    }
+
+    for synonym in &new.synonyms {
+        if !old.synonyms.iter().any(|s| s.label == synonym.label) {
+            changes.push(Change::Added(Item::Synonym(synonym.label.clone())));
+        }
+    }
+
+    for keyword in &old.keywords {
+        match new.keywords.iter().find(|k| k.word == keyword.word) {
+            Some(k) if k != keyword => {
+                changes.push(Change::Changed(Item::Keyword(keyword.word.clone())))
+            }
+            Some(_) => {}
+            None => changes.push(Change::Removed(Item::Keyword(keyword.word.clone()))),
+        }
+    }
+
+    for keyword in &new.keywords {
+        if !old.keywords.iter().any(|k| k.word == keyword.word) {
+            changes.push(Change::Added(Item::Keyword(keyword.word.clone())));
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old: Rules = r#"{
+            "initial": ["hi"],
+            "synon": [{"label": "@be", "list": ["am"]}, {"label": "@sad", "list": ["sad"]}],
+            "key": [
+                {"word": "a", "weight": 0, "decomp": []},
+                {"word": "b", "weight": 0, "decomp": []}
+            ]
+        }"#
+        .parse()
+        .unwrap();
+
+        let new: Rules = r#"{
+            "initial": ["hello"],
+            "synon": [{"label": "@be", "list": ["am", "is"]}],
+            "key": [
+                {"word": "a", "weight": 0, "decomp": []},
+                {"word": "b", "weight": 1, "decomp": []},
+                {"word": "c", "weight": 0, "decomp": []}
+            ]
+        }"#
+        .parse()
+        .unwrap();
+
+        let expected = vec![
+            Change::Changed(Item::Initial),
+            Change::Changed(Item::Synonym("@be".to_string())),
+            Change::Removed(Item::Synonym("@sad".to_string())),
+            Change::Changed(Item::Keyword("b".to_string())),
+            Change::Added(Item::Keyword("c".to_string())),
+        ];
+
+        assert_eq!(expected, diff(&old, &new));
+        assert!(diff(&new, &new).is_empty());
+    }
+}
@@ -1,20 +1,542 @@
-use crate::eliza::Eliza;
-use crate::rules::Rules;
+use crate::commands::{self, CommandParser};
+use crate::permissions::{Author, Permissions};
+use crate::ratelimit::{Decision, RateLimiter};
+use crate::rulesets::{Rulesets, Scope};
+use crate::slash::{Interaction, SlashClient, SlashCommand};
+use crate::store::{Entry, RuleStore};
+use crate::triggers::{Incoming, Triggers};
+use arc_swap::ArcSwap;
+use chrono::NaiveDateTime;
+use eliza::chat::{Event, Reply, Sessions, NO_RULES};
+use eliza::diff::diff;
+use eliza::fetch::{FetchError, Fetcher};
+use eliza::registry::ScriptRegistry;
+use eliza::Eliza;
+use eliza::Rules;
+use serde_json::{json, Value};
 use serenity::{
-    model::{channel::Message, gateway::Ready},
+    model::{channel::Message, gateway::Ready, id::ChannelId},
     prelude::*,
 };
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task;
 
+/// Discord rejects messages longer than 2000 characters.
+const MAX_RESPONSE_LENGTH: usize = 1900;
+
+/// How long the conversation of a channel lasts without messages.
+pub const CONVERSATION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Receives the events of the gateway, and handles them on the runtime so
+/// that the gateway never waits for replies to be sent or rules fetched.
 pub struct Handler {
-    pub eliza: Mutex<Option<Eliza>>,
+    pub bot: Arc<Bot>,
+    pub runtime: Handle,
+}
+
+/// The state of the discord bot, shared by the tasks handling events.
+pub struct Bot {
+    /// Rules loaded with `!load_rules`, shared with the thread reloading them
+    /// when their files change. They are replaced as a whole, so that
+    /// replies never wait for a reload.
+    pub rulesets: Arc<ArcSwap<Rulesets>>,
+    /// Named scripts which can be selected in a channel with `!use <name>`.
+    pub scripts: ScriptRegistry,
+    pub selections: Mutex<HashMap<ChannelId, String>>,
+    /// The conversations of the channels, forgotten after
+    /// `CONVERSATION_TIMEOUT`.
+    pub sessions: Sessions,
     pub bot_id: u64,
-    // TODO: error type
-    pub store: Box<dyn Fn(&Rules) -> bool + Sync + Send>,
+    pub triggers: Triggers,
+    pub commands: CommandParser,
+    pub permissions: Permissions,
+    pub fetcher: Fetcher,
+    pub limiter: RateLimiter,
+    pub store: Box<dyn RuleStore>,
+    pub slash: SlashClient,
+    /// The guild `/eliza` is registered in, or `None` to register it
+    /// globally.
+    pub slash_guild: Option<u64>,
 }
 
-impl EventHandler for Handler {
-    fn message(&self, ctx: Context, msg: Message) {
+/// Who sent a command, and where: with a message or a slash command.
+struct Origin {
+    guild: Option<u64>,
+    channel: u64,
+    author: Author,
+    tag: String,
+}
+
+impl Origin {
+    fn from_message(ctx: &Context, msg: &Message) -> Self {
+        Origin {
+            guild: msg.guild_id.map(|id| id.0),
+            channel: msg.channel_id.0,
+            author: Author {
+                id: msg.author.id.0,
+                roles: match &msg.member {
+                    Some(member) => member.roles.iter().map(|r| r.0).collect(),
+                    None => vec![],
+                },
+                can_manage: match msg.guild(&ctx.cache) {
+                    Some(guild) => {
+                        let permissions = guild.read().member_permissions(msg.author.id);
+                        permissions.manage_guild() || permissions.administrator()
+                    }
+                    None => msg.is_private(),
+                },
+            },
+            tag: msg.author.tag(),
+        }
+    }
+
+    fn from_interaction(interaction: &Interaction) -> Self {
+        Origin {
+            guild: interaction.guild_id,
+            channel: interaction.channel_id,
+            author: Author {
+                id: interaction.user_id,
+                roles: interaction.roles.clone(),
+                can_manage: interaction.can_manage,
+            },
+            tag: interaction.user_tag.clone(),
+        }
+    }
+
+    /// The scope of commands by default: the guild, or the channel in direct
+    /// messages.
+    fn default_scope(&self) -> Scope {
+        match self.guild {
+            Some(guild) => Scope::Guild(guild),
+            None => Scope::Channel(self.channel),
+        }
+    }
+}
+
+/// Rules to fetch, once a load command was checked.
+struct LoadRequest {
+    origin: Origin,
+    scope: Scope,
+    /// The URL of the rules, or the name of the attached file.
+    source: String,
+    /// The URL of the attached file, if any.
+    attachment: Option<String>,
+}
+
+/// What a slash command needs to be answered.
+enum Action {
+    Reply(String),
+    Load(LoadRequest),
+}
+
+/// Runs blocking code, such as the serenity HTTP client or a store, on the
+/// threads of the runtime dedicated to it.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    match task::spawn_blocking(f).await {
+        Ok(value) => Some(value),
+        Err(e) => {
+            eprintln!("Blocking task failed: {}", e);
+            None
+        }
+    }
+}
+
+impl Bot {
+    /// Checks whether the author of a command may run an admin command
+    /// changing the rules of a scope. Denied attempts are logged, and the
+    /// response to send is returned.
+    fn check_permission(&self, origin: &Origin, command: &str, scope: Scope) -> Result<(), String> {
+        if self.permissions.allows(&origin.author, scope) {
+            return Ok(());
+        }
+
+        println!(
+            "Denied {} ({:?}) to {} ({})",
+            command, scope, origin.tag, origin.author.id
+        );
+
+        Err(match scope {
+            Scope::Global => format!("Only the bot admins can use {} globally.", command),
+            _ => format!(
+                "You need the Manage Server permission or an admin role to use {}.",
+                command
+            ),
+        })
+    }
+
+    /// Describes a message for the triggers.
+    fn incoming(&self, ctx: &Context, msg: &Message) -> Incoming {
+        let mentions_bot = msg.mentions.iter().any(|m| m.id == self.bot_id);
+
+        // Only look the replied message up when it matters, as it may take
+        // a request.
+        let replies_to_bot = self.triggers.checks_reply(mentions_bot)
+            && match msg.message_reference.as_ref().and_then(|r| r.message_id) {
+                Some(message_id) => {
+                    let cached = ctx.cache.read().message(msg.channel_id, message_id);
+
+                    let replied = match cached {
+                        Some(replied) => Some(replied),
+                        None => ctx.http.get_message(msg.channel_id.0, message_id.0).ok(),
+                    };
+
+                    replied.is_some_and(|replied| replied.author.id == self.bot_id)
+                }
+                None => false,
+            };
+
+        Incoming {
+            private: msg.is_private(),
+            mentions_bot,
+            replies_to_bot,
+            channel: msg.channel_id.0,
+        }
+    }
+
+    /// Stores rules for a scope and starts answering with them. Returns the
+    /// response to send.
+    fn store(
+        &self,
+        origin: &Origin,
+        scope: Scope,
+        rules: Rules,
+        source: &str,
+        response: String,
+    ) -> String {
+        match self.store.save(scope, &rules, &origin.tag, source) {
+            Ok(_) => {
+                let eliza = Eliza::new(rules);
+
+                self.rulesets.rcu(|rulesets| {
+                    let mut rulesets = Rulesets::clone(rulesets);
+                    rulesets.insert(scope, eliza.clone());
+                    rulesets
+                });
+
+                response
+            }
+            Err(e) => {
+                eprintln!("Failed to store new rules: {}", e);
+                "Internal error..".to_string()
+            }
+        }
+    }
+
+    /// Stores fetched rules, and returns the response to send.
+    fn load_rules(
+        &self,
+        origin: &Origin,
+        scope: Scope,
+        source: &str,
+        result: Result<Rules, FetchError>,
+    ) -> String {
+        match result {
+            Ok(rules) => {
+                let summary = summary(&rules);
+                self.store(origin, scope, rules, source, summary)
+            }
+            Err(e) => {
+                println!("Could not load rules at {}: {:?}", source, e);
+                e.to_string()
+            }
+        }
+    }
+
+    /// Checks `!load_rules [channel|global] <url>`, or with the rules as an
+    /// attachment, and returns the rules to fetch, or the response to send.
+    /// The rules apply to the whole guild by default, or to the channel in
+    /// direct messages.
+    fn load_rules_command(
+        &self,
+        origin: Origin,
+        msg: &Message,
+        mut words: Vec<&str>,
+    ) -> Result<LoadRequest, String> {
+        let scope = take_scope(&origin, &mut words);
+        let command = format!("{}load_rules", self.commands.prefix);
+
+        self.check_permission(&origin, &command, scope)?;
+
+        let (source, attachment) = match msg.attachments.first() {
+            Some(attachment) => (attachment.filename.clone(), Some(attachment.url.clone())),
+            None if words.is_empty() => {
+                return Err(format!("Usage: {}", self.commands.usage("load_rules")))
+            }
+            None => (words.join(" "), None),
+        };
+
+        Ok(LoadRequest {
+            origin,
+            scope,
+            source,
+            attachment,
+        })
+    }
+
+    /// Fetches rules and stores them, and returns the response to send.
+    async fn load(self: Arc<Self>, request: LoadRequest) -> String {
+        let result = match &request.attachment {
+            Some(url) => self.fetcher.fetch_attachment(&request.source, url).await,
+            None => self.fetcher.fetch(&request.source).await,
+        };
+
+        let bot = Arc::clone(&self);
+
+        blocking(move || bot.load_rules(&request.origin, request.scope, &request.source, result))
+            .await
+            .unwrap_or_else(|| "Internal error..".to_string())
+    }
+
+    /// Runs `!use [name|default]`, and returns the response to send.
+    fn use_command(&self, origin: &Origin, words: Vec<&str>) -> String {
+        let name = words.join(" ");
+        let scope = Scope::Channel(origin.channel);
+        let command = format!("{}use", self.commands.prefix);
+
+        if name.is_empty() {
+            let names: Vec<&str> = self.scripts.names().collect();
+            return format!("Available scripts: {}", names.join(", "));
+        }
+
+        if let Err(denied) = self.check_permission(origin, &command, scope) {
+            return denied;
+        }
+
+        if name == "default" {
+            self.selections
+                .lock()
+                .unwrap()
+                .remove(&ChannelId(origin.channel));
+            "Using the default script.".to_string()
+        } else if self.scripts.contains(&name) {
+            let greeting = self.scripts.get(&name).unwrap().greeting().to_string();
+            self.selections
+                .lock()
+                .unwrap()
+                .insert(ChannelId(origin.channel), name);
+            greeting
+        } else {
+            format!("Unknown script '{}'.", name)
+        }
+    }
+
+    /// Runs `!rules [channel|global] history|rollback <n>|diff <n>`, and
+    /// returns the response to send.
+    fn rules_command(&self, origin: &Origin, mut words: Vec<&str>) -> String {
+        let scope = take_scope(origin, &mut words);
+        let command = format!("{}rules", self.commands.prefix);
+
+        if let Err(denied) = self.check_permission(origin, &command, scope) {
+            return denied;
+        }
+
+        let history = match self.store.history(scope) {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Could not read the history of {:?}: {}", scope, e);
+                return "Internal error..".to_string();
+            }
+        };
+
+        let usage = format!("Usage: {}", self.commands.usage("rules"));
+
+        let response = match words.as_slice() {
+            ["history"] if history.is_empty() => "No rules were loaded here.".to_string(),
+            ["history"] => history
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| describe(i + 1, entry))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            [command @ "rollback", n] | [command @ "diff", n] => {
+                let entry = match n.parse::<usize>() {
+                    Ok(n) if n >= 1 && n <= history.len() => &history[n - 1],
+                    _ => return format!("There is no version #{} in the history.", n),
+                };
+
+                if *command == "rollback" {
+                    let source = format!("rollback to #{}", n);
+                    let response = format!(
+                        "OK! Rolled back to {}.",
+                        describe(n.parse().unwrap(), entry)
+                    );
+                    self.store(origin, scope, entry.rules.clone(), &source, response)
+                } else {
+                    let changes = diff(&entry.rules, &history[0].rules);
+
+                    if changes.is_empty() {
+                        format!("Version #{} is the same as the current rules.", n)
+                    } else {
+                        let changes: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+                        format!("Changes since #{}:\n{}", n, changes.join("\n"))
+                    }
+                }
+            }
+            _ => usage,
+        };
+
+        truncate(response)
+    }
+
+    /// Calls `f` with the script answering in a channel: the selected one, or
+    /// else the loaded rules, or else the default script.
+    fn with_eliza<T>(&self, origin: &Origin, f: impl FnOnce(&Eliza) -> T) -> Option<T> {
+        let rulesets = self.rulesets.load();
+
+        let selection = self
+            .selections
+            .lock()
+            .unwrap()
+            .get(&ChannelId(origin.channel))
+            .cloned();
+
+        selection
+            .and_then(|name| self.scripts.get(&name))
+            .or_else(|| rulesets.get(origin.guild, origin.channel))
+            .or_else(|| self.scripts.default_script())
+            .map(f)
+    }
+
+    /// Answers a message which is not a command, in the conversation of its
+    /// channel. Its mentions of the bot must be removed first, as the
+    /// sessions only clean up whitespace. Empty messages get no reply.
+    fn reply(&self, origin: &Origin, text: &str) -> Option<String> {
+        println!("MESSAGE: {}", text);
+
+        let conversation = origin.channel.to_string();
+        let event = Event::Message {
+            conversation: conversation.clone(),
+            text: text.to_string(),
+        };
+
+        self.with_eliza(origin, |eliza| self.sessions.handle(eliza, event))
+            .unwrap_or_else(|| Some(Reply::no_rules(&conversation)))
+            .map(|reply| reply.text)
+    }
+
+    /// Runs a slash command, except for fetching rules.
+    fn slash_command(&self, interaction: &Interaction) -> Action {
+        let origin = Origin::from_interaction(interaction);
+
+        let response = match &interaction.command {
+            SlashCommand::Talk(text) => self
+                .reply(&origin, text)
+                .unwrap_or_else(|| "What would you like to say?".to_string()),
+            SlashCommand::Trace(text) => self
+                .with_eliza(&origin, |eliza| format!("```\n{}\n```", eliza.trace(text)))
+                .map(truncate)
+                .unwrap_or_else(|| NO_RULES.to_string()),
+            SlashCommand::Reset => {
+                let scope = Scope::Channel(origin.channel);
+
+                if let Err(denied) = self.check_permission(&origin, "/eliza reset", scope) {
+                    return Action::Reply(denied);
+                }
+
+                self.selections
+                    .lock()
+                    .unwrap()
+                    .remove(&ChannelId(origin.channel));
+
+                let conversation = origin.channel.to_string();
+                let event = Event::Open(conversation.clone());
+
+                self.with_eliza(&origin, |eliza| self.sessions.handle(eliza, event))
+                    .flatten()
+                    .unwrap_or_else(|| Reply::no_rules(&conversation))
+                    .text
+            }
+            SlashCommand::Load(url, scope) => {
+                let scope = match scope.as_deref() {
+                    Some("channel") => Scope::Channel(origin.channel),
+                    Some("global") => Scope::Global,
+                    _ => origin.default_scope(),
+                };
+
+                if let Err(denied) = self.check_permission(&origin, "/eliza load", scope) {
+                    return Action::Reply(denied);
+                }
+
+                return Action::Load(LoadRequest {
+                    origin,
+                    scope,
+                    source: url.clone(),
+                    attachment: None,
+                });
+            }
+        };
+
+        Action::Reply(response)
+    }
+
+    /// Answers an `INTERACTION_CREATE` event.
+    async fn on_interaction(self: Arc<Self>, raw: Value) {
+        let interaction = match Interaction::parse(&raw) {
+            Some(interaction) => interaction,
+            None => return,
+        };
+
+        println!(
+            "interaction = {{author: {}, command: {:?}}}",
+            interaction.user_tag, interaction.command
+        );
+
+        let load = matches!(interaction.command, SlashCommand::Load(..));
+
+        // Interactions must always be answered.
+        if let Decision::Deny { retry_after, .. } = self.limiter.check(
+            interaction.user_id,
+            interaction.channel_id,
+            interaction.guild_id,
+            load,
+        ) {
+            println!(
+                "Rate limited {} ({})",
+                interaction.user_tag, interaction.user_id
+            );
+
+            if let Err(why) = self
+                .slash
+                .respond(&interaction, &cooldown(retry_after))
+                .await
+            {
+                println!("Error answering interaction: {}", why);
+            }
+
+            return;
+        }
+
+        let action = {
+            let bot = Arc::clone(&self);
+            let interaction = interaction.clone();
+            blocking(move || bot.slash_command(&interaction)).await
+        };
+
+        // Interactions must be answered within 3 seconds, which fetching
+        // rules may exceed.
+        let result = match action {
+            Some(Action::Reply(response)) => self.slash.respond(&interaction, &response).await,
+            Some(Action::Load(request)) => match self.slash.defer(&interaction).await {
+                Ok(_) => {
+                    let response = Arc::clone(&self).load(request).await;
+                    self.slash.edit_response(&interaction, &response).await
+                }
+                Err(e) => Err(e),
+            },
+            None => self.slash.respond(&interaction, "Internal error..").await,
+        };
+
+        if let Err(why) = result {
+            println!("Error answering interaction: {}", why);
+        }
+    }
+
+    /// Answers a message, except for fetching rules: the rules to fetch are
+    /// returned instead.
+    fn handle_message(&self, ctx: &Context, msg: &Message) -> Option<LoadRequest> {
         println!(
             "msg = {{author: {}, content: '{}', mentions: [{}]}}",
             &msg.author,
@@ -26,83 +548,298 @@ impl EventHandler for Handler {
                 .join(", ")
         );
 
-        if msg.mentions.len() != 1 {
-            return;
+        if msg.author.id == self.bot_id || msg.author.bot {
+            return None;
         }
 
-        if msg.mentions[0].id != self.bot_id {
-            return;
+        if !self.triggers.matches(&self.incoming(ctx, msg)) {
+            return None;
         }
 
-        if msg.content.contains("!load_rules") {
-            let url = {
-                let mut url = msg.content.replace("!load_rules", "");
-                url = url.replace(&format!("<@!{}>", self.bot_id), "");
-                url.trim().to_string()
-            };
+        let text = commands::strip_mentions(&msg.content, self.bot_id);
+        let origin = Origin::from_message(ctx, msg);
+        let command = self.commands.parse(&text);
+        let load = command.as_ref().is_some_and(|c| c.name == "load_rules");
 
-            if let Err(why) = msg
-                .channel_id
-                .say(&ctx.http, &format!("Loading rules at {} ...", &url))
-            {
-                println!("Error sending message: {:?}", why);
+        if let Decision::Deny { retry_after, warn } =
+            self.limiter
+                .check(origin.author.id, origin.channel, origin.guild, load)
+        {
+            println!("Rate limited {} ({})", origin.tag, origin.author.id);
+
+            if warn {
+                say(ctx, msg, &cooldown(retry_after));
             }
 
-            // TODO: map
-            let body: Result<String, _> = match reqwest::blocking::get(&url) {
-                Ok(response) => response.text(),
-                Err(e) => Err(e),
-            };
+            return None;
+        }
 
-            let response = match body {
-                Ok(body) => {
-                    let rules: Rules =
-                        serde_json::from_str(&body).expect("Could not parse JSON file.");
+        let response = match command {
+            Some(command) => {
+                let args: Vec<&str> = command.args.iter().map(String::as_str).collect();
 
-                    if (self.store)(&rules) {
-                        let new_eliza = Eliza::new(rules);
-                        let mut guard = self.eliza.lock().unwrap();
-                        *guard = Some(new_eliza);
-                        "OK!"
-                    } else {
-                        eprintln!("Failed to store new rules.");
-                        "Internal error.."
-                    }
+                match command.name.as_str() {
+                    "help" => self.commands.help(),
+                    "ping" => "Pong!".to_string(),
+                    "load_rules" => match self.load_rules_command(origin, msg, args) {
+                        Ok(request) => {
+                            say(
+                                ctx,
+                                msg,
+                                &format!("Loading rules at {} ...", &request.source),
+                            );
+                            return Some(request);
+                        }
+                        Err(response) => response,
+                    },
+                    "rules" => self.rules_command(&origin, args),
+                    "use" => self.use_command(&origin, args),
+                    name => format!(
+                        "Unknown command '{}{}'. Try {}.",
+                        self.commands.prefix,
+                        name,
+                        self.commands.usage("help")
+                    ),
                 }
-                Err(_) => "Error loading rules.. Please check your URL.",
-            };
-
-            if let Err(why) = msg.channel_id.say(&ctx.http, response) {
-                println!("Error sending message: {:?}", why);
-            }
-        } else if msg.content.contains("!ping") {
-            if let Err(why) = msg.channel_id.say(&ctx.http, "Pong!") {
-                println!("Error sending message: {:?}", why);
             }
-        } else {
-            // Generate response
-            let guard = self.eliza.lock().unwrap();
+            // Empty messages, such as a lone mention, get no reply.
+            None => self.reply(&origin, &text)?,
+        };
 
-            let message = msg
-                .content
-                .replace(&format!("<@!{}>", self.bot_id), "")
-                .trim()
-                .to_string();
+        say(ctx, msg, &response);
+        None
+    }
 
-            println!("MESSAGE: {}", message);
+    async fn on_message(self: Arc<Self>, ctx: Context, msg: Message) {
+        let request = {
+            let bot = Arc::clone(&self);
+            let (ctx, msg) = (ctx.clone(), msg.clone());
+            blocking(move || bot.handle_message(&ctx, &msg)).await
+        };
 
-            let response = match guard.as_ref() {
-                Some(eliza) => eliza.interact(&message).message,
-                None => "Error: no rules defined".to_string(),
-            };
+        if let Some(Some(request)) = request {
+            let response = self.load(request).await;
+            blocking(move || say(&ctx, &msg, &response)).await;
+        }
+    }
+}
 
-            if let Err(why) = msg.channel_id.say(&ctx.http, &response) {
-                println!("Error sending message: {:?}", why);
-            }
+/// Removes the scope a command applies to from its arguments:
+/// `channel`, `global`, or else the default scope of the origin.
+fn take_scope(origin: &Origin, words: &mut Vec<&str>) -> Scope {
+    match words.first() {
+        Some(&"channel") => {
+            words.remove(0);
+            Scope::Channel(origin.channel)
+        }
+        Some(&"global") => {
+            words.remove(0);
+            Scope::Global
+        }
+        _ => origin.default_scope(),
+    }
+}
+
+/// Answers a message with Discord's reply feature, so that conversations are
+/// threaded, without pinging its author.
+fn say(ctx: &Context, msg: &Message, text: &str) {
+    let body = json!({
+        "content": text,
+        "message_reference": { "message_id": msg.id.0.to_string() },
+        "allowed_mentions": { "parse": [], "replied_user": false },
+    });
+
+    if let Err(why) = ctx.http.send_message(msg.channel_id.0, &body) {
+        println!("Error sending message: {:?}", why);
+    }
+}
+
+/// Tells a user to wait before being answered again.
+fn cooldown(retry_after: Duration) -> String {
+    let seconds = retry_after.as_secs_f64().ceil() as u64;
+    format!("Slow down! Try again in {} seconds.", seconds.max(1))
+}
+
+/// Describes an entry of the history of a scope.
+fn describe(number: usize, entry: &Entry) -> String {
+    let time = NaiveDateTime::from_timestamp(entry.timestamp as i64, 0);
+
+    format!(
+        "#{} {} by {} from {}",
+        number,
+        time.format("%Y-%m-%d %H:%M UTC"),
+        entry.user,
+        entry.source
+    )
+}
+
+/// Cuts a response to fit in a Discord message.
+fn truncate(mut response: String) -> String {
+    if response.len() > MAX_RESPONSE_LENGTH {
+        let mut end = MAX_RESPONSE_LENGTH;
+
+        while !response.is_char_boundary(end) {
+            end -= 1;
         }
+
+        response.truncate(end);
+        response.push_str("\n...");
+    }
+
+    response
+}
+
+/// Describes rules which were just loaded.
+fn summary(rules: &Rules) -> String {
+    let mut summary = format!(
+        "OK! Loaded {} keywords and {} synonyms.",
+        rules.keywords.len(),
+        rules.synonyms.len()
+    );
+
+    // Stay well below the length limit of Discord messages.
+    let warnings = rules.warnings();
+
+    for warning in warnings.iter().take(10) {
+        summary.push_str(&format!("\nWarning: {}", warning));
+    }
+
+    if warnings.len() > 10 {
+        summary.push_str(&format!("\n... and {} more warnings.", warnings.len() - 10));
+    }
+
+    summary
+}
+
+impl EventHandler for Handler {
+    fn message(&self, ctx: Context, msg: Message) {
+        self.runtime
+            .spawn(Arc::clone(&self.bot).on_message(ctx, msg));
     }
 
     fn ready(&self, _: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
+
+        let bot = Arc::clone(&self.bot);
+
+        self.runtime.spawn(async move {
+            match bot.slash.register(bot.slash_guild).await {
+                Ok(_) => println!("Registered the /eliza command."),
+                Err(why) => println!("Could not register the /eliza command: {}", why),
+            }
+        });
+    }
+
+    fn unknown(&self, _: Context, name: String, raw: Value) {
+        if name == "INTERACTION_CREATE" {
+            self.runtime
+                .spawn(Arc::clone(&self.bot).on_interaction(raw));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratelimit::Limits;
+    use crate::store::MemoryStore;
+    use tokio::runtime::Runtime;
+
+    fn bot() -> Bot {
+        let (therapist, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut scripts = ScriptRegistry::new();
+        scripts.insert("therapist".to_string(), Eliza::new(therapist));
+
+        Bot {
+            rulesets: Arc::new(ArcSwap::from_pointee(Rulesets::new())),
+            scripts,
+            selections: Mutex::new(HashMap::new()),
+            sessions: Sessions::with_idle_timeout(CONVERSATION_TIMEOUT),
+            bot_id: 1,
+            triggers: Triggers::default(),
+            commands: CommandParser::default(),
+            permissions: Permissions::default(),
+            fetcher: Fetcher::default(),
+            limiter: RateLimiter::new(Limits::default()),
+            store: Box::new(MemoryStore::new()),
+            slash: SlashClient::new("token", 1),
+            slash_guild: None,
+        }
+    }
+
+    fn interaction(command: SlashCommand, can_manage: bool) -> Interaction {
+        Interaction {
+            id: "100".to_string(),
+            token: "abc".to_string(),
+            guild_id: Some(2),
+            channel_id: 20,
+            user_id: 42,
+            user_tag: "ada#0001".to_string(),
+            roles: vec![],
+            can_manage,
+            command,
+        }
+    }
+
+    fn reply(action: Action) -> String {
+        match action {
+            Action::Reply(response) => response,
+            Action::Load(request) => panic!("expected a reply, got a load of {}", request.source),
+        }
+    }
+
+    #[test]
+    fn test_slash_talk_and_trace() {
+        let bot = bot();
+
+        let talk = interaction(SlashCommand::Talk("I am sad".to_string()), false);
+        assert!(!reply(bot.slash_command(&talk)).is_empty());
+
+        let trace = interaction(SlashCommand::Trace("I am sad".to_string()), false);
+        let response = reply(bot.slash_command(&trace));
+        assert!(response.contains("input: i am sad"));
+        assert!(response.contains("response: "));
+    }
+
+    #[test]
+    fn test_slash_reset() {
+        let bot = bot();
+        bot.selections
+            .lock()
+            .unwrap()
+            .insert(ChannelId(20), "other".to_string());
+
+        let denied = interaction(SlashCommand::Reset, false);
+        assert!(reply(bot.slash_command(&denied)).contains("Manage Server"));
+        assert_eq!(1, bot.selections.lock().unwrap().len());
+
+        let allowed = interaction(SlashCommand::Reset, true);
+        bot.slash_command(&allowed);
+        assert!(bot.selections.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_slash_load() {
+        let bot = Arc::new(bot());
+
+        let global = SlashCommand::Load(
+            "https://example.com/rules.json".to_string(),
+            Some("global".to_string()),
+        );
+        let response = reply(bot.slash_command(&interaction(global, true)));
+        assert!(response.starts_with("Only the bot admins"));
+
+        let invalid = SlashCommand::Load("ftp://example.com/rules.json".to_string(), None);
+        let request = match bot.slash_command(&interaction(invalid, true)) {
+            Action::Load(request) => request,
+            Action::Reply(response) => panic!("expected a load, got {}", response),
+        };
+        assert_eq!(Scope::Guild(2), request.scope);
+
+        let response = Runtime::new()
+            .unwrap()
+            .block_on(Arc::clone(&bot).load(request));
+        assert_eq!("Only http and https URLs are allowed, not ftp.", response);
+        assert!(bot.store.list().unwrap().is_empty());
     }
 }
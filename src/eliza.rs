@@ -1,52 +1,147 @@
 use crate::pattern::match_pattern;
-use crate::rules::{Decomposition, Replacement, Rules};
+use crate::rules::{Replacement, Rules};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 pub struct Eliza {
-    rules: Rules,
+    /// Shared between clones, which only copy the state of the choices.
+    rules: Arc<Rules>,
+    /// The index of each keyword in the rules given to `Eliza::new`, before
+    /// they were sorted.
+    positions: Vec<usize>,
+    choice: Choice,
+}
+
+/// How Eliza chooses among the items of a rule.
+enum Choice {
+    Random,
+    /// Makes the responses reproducible, see `Eliza::seed`.
+    Seeded(Box<Mutex<StdRng>>),
+    /// The next position in each list of items, keyed by its address, which
+    /// does not change as the rules are never modified. See `Eliza::cycle`.
+    Cycle(Mutex<HashMap<usize, usize>>),
+}
+
+impl Clone for Eliza {
+    fn clone(&self) -> Self {
+        let choice = match &self.choice {
+            Choice::Random => Choice::Random,
+            Choice::Seeded(rng) => {
+                Choice::Seeded(Box::new(Mutex::new(rng.lock().unwrap().clone())))
+            }
+            // A copy starts over, like a new conversation.
+            Choice::Cycle(_) => Choice::Cycle(Mutex::default()),
+        };
+
+        Eliza {
+            rules: Arc::clone(&self.rules),
+            positions: self.positions.clone(),
+            choice,
+        }
+    }
 }
 
 impl Eliza {
     pub fn new(mut rules: Rules) -> Self {
-        rules.sort_keywords_by_reverse_weight();
-        Self { rules }
+        let positions = rules.sort_keywords_by_reverse_weight();
+        Self {
+            rules: Arc::new(rules),
+            positions,
+            choice: Choice::Random,
+        }
+    }
+
+    /// Makes Eliza choose its responses in the same order for the same seed.
+    pub fn seed(&mut self, seed: u64) {
+        self.choice = Choice::Seeded(Box::new(Mutex::new(StdRng::seed_from_u64(seed))));
+    }
+
+    /// Makes Eliza use the items of each rule in turn, like the original
+    /// ELIZA did.
+    pub fn cycle(&mut self) {
+        self.choice = Choice::Cycle(Mutex::default());
+    }
+
+    /// Chooses one of the items, according to the strategy of `self.choice`.
+    fn choose<'a>(&self, items: &'a [String]) -> Option<&'a String> {
+        match &self.choice {
+            Choice::Random => items.choose(&mut rand::thread_rng()),
+            Choice::Seeded(rng) => items.choose(&mut *rng.lock().unwrap()),
+            Choice::Cycle(positions) => {
+                if items.is_empty() {
+                    return None;
+                }
+
+                let mut positions = positions.lock().unwrap();
+                let position = positions.entry(items.as_ptr() as usize).or_insert(0);
+                let item = &items[*position % items.len()];
+                *position += 1;
+                Some(item)
+            }
+        }
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
     }
 
     pub fn greeting(&self) -> &str {
-        self.rules
-            .initial
-            .choose(&mut rand::thread_rng())
+        self.choose(&self.rules.initial)
             .expect("initial rules should have at least one item")
     }
 
     pub fn interact(&self, input: &str) -> Response {
+        self.trace(input).response
+    }
+
+    /// Responds to the input like `Eliza::interact`, and tells how the
+    /// response was chosen.
+    pub fn trace(&self, input: &str) -> Trace {
         let input = input.trim().to_lowercase();
 
         if self.rules.quit.contains(&input) {
             let message = self
-                .rules
-                .final_
-                .choose(&mut rand::thread_rng())
+                .choose(&self.rules.final_)
                 .expect("final rules should have at least one item");
 
-            return Response::farewell(message.to_string());
+            return Trace {
+                input,
+                quit: true,
+                ..Trace::new(Response::farewell(message.to_string()))
+            };
         }
 
         let input = Eliza::rewrite(&input, &self.rules.pre);
 
-        let message = match self.apply_best_matching_pattern(&input) {
-            Some((groups, decomposition)) => {
-                let template = decomposition
-                    .reasmb
-                    .choose(&mut rand::thread_rng())
+        match self.apply_best_matching_pattern(&input) {
+            Some((groups, keyword, decomposition)) => {
+                let keyword = &self.rules.keywords[keyword];
+                let decomposition = &keyword.decomposition[decomposition];
+                let template = self
+                    .choose(&decomposition.reasmb)
                     .expect("reasmb rules should have at least one item");
 
-                self.format_template(&template, &groups)
-            }
-            None => self.random_response().to_string(),
-        };
+                let message = self.format_template(template, &groups);
 
-        Response::normal(message)
+                Trace {
+                    keyword: Some(keyword.word.clone()),
+                    pattern: Some(decomposition.pattern.clone()),
+                    groups: groups.iter().map(|g| g.to_string()).collect(),
+                    template: Some(template.clone()),
+                    input,
+                    ..Trace::new(Response::normal(message))
+                }
+            }
+            None => Trace {
+                input,
+                ..Trace::new(Response::normal(self.random_response().to_string()))
+            },
+        }
     }
 
     fn random_response(&self) -> &str {
@@ -57,33 +152,42 @@ impl Eliza {
             .find(|k| k.word == "xnone")
             .expect("The keyword 'xnone' was not found.");
 
-        keyword.decomposition[0]
-            .reasmb
-            .choose(&mut rand::thread_rng())
+        self.choose(&keyword.decomposition[0].reasmb)
             .expect("the 'xnone' keyword reasmb does not have any items")
     }
 
-    fn apply_best_matching_pattern<'a, 'b>(
-        &'b self,
+    /// Finds the keyword and the decomposition answering an input, and the
+    /// groups captured by its pattern, like `Eliza::trace` without choosing
+    /// a response. Quit words are not taken into account.
+    pub fn route(&self, input: &str) -> Option<Route> {
+        let input = Eliza::rewrite(&input.trim().to_lowercase(), &self.rules.pre);
+        let (groups, keyword, decomposition) = self.apply_best_matching_pattern(&input)?;
+
+        Some(Route {
+            keyword: self.positions[keyword],
+            decomposition,
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        })
+    }
+
+    /// Returns the index of the keyword and of the decomposition matching the
+    /// input, and the groups captured by its pattern.
+    fn apply_best_matching_pattern<'a>(
+        &self,
         input: &'a str,
-    ) -> Option<(Vec<&'a str>, &'b Decomposition)> {
+    ) -> Option<(Vec<&'a str>, usize, usize)> {
         // NOTE: the rules are sorted in Eliza::new to have the highest weight first.
 
-        for keyword in &self.rules.keywords {
+        for (k, keyword) in self.rules.keywords.iter().enumerate() {
             if !input.contains(&keyword.word) {
                 continue;
             }
 
-            for decomposition in &keyword.decomposition {
-                let result = match_pattern(&decomposition.pattern, &input, &self.rules.synonyms);
+            for (d, decomposition) in keyword.decomposition.iter().enumerate() {
+                let result = match_pattern(&decomposition.pattern, input, &self.rules.synonyms);
 
                 if let Some(groups) = result {
-                    // println!(
-                    //     "[keyword={}, pattern={}]",
-                    //     keyword.word, decomposition.pattern
-                    // );
-
-                    return Some((groups, decomposition));
+                    return Some((groups, k, d));
                 }
             }
         }
@@ -91,34 +195,34 @@ impl Eliza {
         None
     }
 
-    fn format_template(&self, template: &str, groups: &Vec<&str>) -> String {
+    fn format_template(&self, template: &str, groups: &[&str]) -> String {
         let mut output = String::new();
 
         for template_part in template.split(" ") {
             if template_part.starts_with("(") {
                 // TODO: support group numbers > 9
-                let group_number = &template_part[1..2];
+                let group_number = template_part.get(1..2).map(str::parse::<usize>);
 
-                if let Ok(group_number) = group_number.parse::<usize>() {
+                if let Some(Ok(group_number)) = group_number {
                     let group = groups[group_number - 1];
                     output.push_str(&Eliza::rewrite(group, &self.rules.post));
-                    output.push_str(" ");
+                    output.push(' ');
                     continue;
                 }
             }
 
             output.push_str(template_part);
-            output.push_str(" ");
+            output.push(' ');
         }
 
         output.pop();
         output
     }
 
-    fn rewrite(input: &str, replacements: &Vec<Replacement>) -> String {
+    fn rewrite(input: &str, replacements: &[Replacement]) -> String {
         input
             .split(" ")
-            .map(|w| match replacements.iter().find(|r| &r.from == w) {
+            .map(|w| match replacements.iter().find(|r| r.from == w) {
                 Some(r) => r.to.clone(),
                 None => w.to_string(),
             })
@@ -127,7 +231,7 @@ impl Eliza {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct Response {
     pub is_farewell: bool,
     pub message: String,
@@ -149,10 +253,72 @@ impl Response {
     }
 }
 
+/// The keyword and the decomposition answering an input, see `Eliza::route`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Route {
+    /// The index of the keyword in the rules given to `Eliza::new`.
+    pub keyword: usize,
+    /// The index of the decomposition in its keyword.
+    pub decomposition: usize,
+    pub groups: Vec<String>,
+}
+
+/// How Eliza chose a response, to debug rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trace {
+    /// The input, lowercased and rewritten with the `pre` replacements.
+    pub input: String,
+    /// Whether the input is a quit word.
+    pub quit: bool,
+    /// The keyword whose decomposition matched, if any.
+    pub keyword: Option<String>,
+    pub pattern: Option<String>,
+    pub groups: Vec<String>,
+    pub template: Option<String>,
+    #[serde(skip)]
+    pub response: Response,
+}
+
+impl Trace {
+    fn new(response: Response) -> Self {
+        Trace {
+            input: String::new(),
+            quit: false,
+            keyword: None,
+            pattern: None,
+            groups: vec![],
+            template: None,
+            response,
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "input: {}", self.input)?;
+
+        if self.quit {
+            writeln!(f, "quit word")?;
+        } else if let (Some(keyword), Some(pattern)) = (&self.keyword, &self.pattern) {
+            writeln!(f, "keyword: {}", keyword)?;
+            writeln!(f, "pattern: {}", pattern)?;
+            writeln!(f, "groups: {:?}", self.groups)?;
+
+            if let Some(template) = &self.template {
+                writeln!(f, "template: {}", template)?;
+            }
+        } else {
+            writeln!(f, "no matching keyword, using xnone")?;
+        }
+
+        write!(f, "response: {}", self.response.message)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::rules::Keyword;
+    use crate::rules::{Decomposition, Keyword};
 
     /// Simple 'xnone' keyword (which are used for random messages), which
     /// only has one message: 'random'.
@@ -163,7 +329,9 @@ mod tests {
             decomposition: vec![Decomposition {
                 pattern: "*".to_string(),
                 reasmb: vec!["random".to_string()],
+                examples: vec![],
             }],
+            ..Default::default()
         }
     }
 
@@ -177,6 +345,7 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords: vec![xnone()],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -199,6 +368,7 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords: vec![],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -215,6 +385,7 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords: vec![xnone()],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -237,7 +408,9 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "test *".to_string(),
                         reasmb: vec!["test".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
                 xnone(),
                 Keyword {
@@ -246,22 +419,115 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "* hello".to_string(),
                         reasmb: vec!["hello".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
 
-        let (groups, decomposition) = eliza.apply_best_matching_pattern("test hello").unwrap();
+        let (groups, keyword, decomposition) =
+            eliza.apply_best_matching_pattern("test hello").unwrap();
+        assert_eq!("hello", eliza.rules.keywords[keyword].word);
         assert_eq!(vec!["test"], groups);
-        assert_eq!("hello".to_string(), decomposition.reasmb[0]);
+        assert_eq!(0, decomposition);
 
-        let (groups, decomposition) = eliza.apply_best_matching_pattern("test 123").unwrap();
+        let (groups, keyword, decomposition) =
+            eliza.apply_best_matching_pattern("test 123").unwrap();
+        assert_eq!("test", eliza.rules.keywords[keyword].word);
         assert_eq!(vec!["123"], groups);
-        assert_eq!("test".to_string(), decomposition.reasmb[0]);
+        assert_eq!(0, decomposition);
 
         assert!(eliza.apply_best_matching_pattern("testing").is_none());
+
+        // Routes refer to the keywords in the order given to Eliza::new.
+        let route = eliza.route("test hello").unwrap();
+        assert_eq!((2, 0), (route.keyword, route.decomposition));
+        assert_eq!(vec!["test"], route.groups);
+    }
+
+    #[test]
+    fn test_trace() {
+        let rules = Rules {
+            final_: vec!["goodbye".to_string()],
+            quit: vec!["quit".to_string()],
+            pre: vec![Replacement {
+                from: "hi".to_string(),
+                to: "hello".to_string(),
+            }],
+            keywords: vec![
+                xnone(),
+                Keyword {
+                    word: "hello".to_string(),
+                    weight: 2,
+                    decomposition: vec![Decomposition {
+                        pattern: "* hello".to_string(),
+                        reasmb: vec!["hello (1)".to_string()],
+                        examples: vec![],
+                    }],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let eliza = Eliza::new(rules);
+
+        let trace = eliza.trace("Well Hi");
+        assert_eq!("well hello", trace.input);
+        assert_eq!(Some("hello".to_string()), trace.keyword);
+        assert_eq!(Some("* hello".to_string()), trace.pattern);
+        assert_eq!(vec!["well"], trace.groups);
+        assert_eq!("hello well", trace.response.message);
+        assert!(trace.to_string().ends_with("response: hello well"));
+
+        let trace = eliza.trace("nothing");
+        assert_eq!(None, trace.keyword);
+        assert_eq!("random", trace.response.message);
+
+        assert!(eliza.trace("quit").quit);
+    }
+
+    #[test]
+    fn test_seed() {
+        let rules = Rules {
+            initial: (0..10).map(|i| i.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let greetings = |seed| {
+            let mut eliza = Eliza::new(rules.clone());
+            eliza.seed(seed);
+            (0..10)
+                .map(|_| eliza.greeting().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(greetings(1), greetings(1));
+        assert_ne!(greetings(1), greetings(2));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let rules = Rules {
+            initial: vec!["a".to_string(), "b".to_string()],
+            final_: vec!["c".to_string(), "d".to_string()],
+            quit: vec!["bye".to_string()],
+            ..Default::default()
+        };
+
+        let mut eliza = Eliza::new(rules);
+        eliza.cycle();
+
+        let greetings: Vec<_> = (0..5).map(|_| eliza.greeting().to_string()).collect();
+        assert_eq!(vec!["a", "b", "a", "b", "a"], greetings);
+
+        // Each list has its own position.
+        assert_eq!("c", eliza.interact("bye").message);
+        assert_eq!("b", eliza.greeting());
     }
 
     #[test]
@@ -277,15 +543,20 @@ mod tests {
             }],
             synonyms: vec![],
             keywords: vec![],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
 
-        let output = eliza.format_template("test (1)", &vec!["123"]);
+        let output = eliza.format_template("test (1)", &["123"]);
         assert_eq!("test 123", &output);
 
-        let output = eliza.format_template("(1) test (3)", &vec!["123", "", "i"]);
+        let output = eliza.format_template("(1) test (3)", &["123", "", "i"]);
         assert_eq!("123 test you", &output);
+
+        // Parentheses without a group number are kept as they are.
+        let output = eliza.format_template("( test (é)", &["123"]);
+        assert_eq!("( test (é)", &output);
     }
 
     #[test]
@@ -315,7 +586,9 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "test *".to_string(),
                         reasmb: vec!["test".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
                 xnone(),
                 Keyword {
@@ -324,9 +597,12 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "* hello".to_string(),
                         reasmb: vec!["hello".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -351,7 +627,9 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "*".to_string(),
                         reasmb: vec!["hello".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
                 xnone(),
                 Keyword {
@@ -360,9 +638,12 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "*".to_string(),
                         reasmb: vec!["world".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -388,9 +669,12 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "* world".to_string(),
                         reasmb: vec!["(1) world".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -415,7 +699,9 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "*".to_string(),
                         reasmb: vec!["hello".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
                 xnone(),
                 Keyword {
@@ -424,9 +710,12 @@ mod tests {
                     decomposition: vec![Decomposition {
                         pattern: "*".to_string(),
                         reasmb: vec!["world".to_string()],
+                        examples: vec![],
                     }],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -448,6 +737,7 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords: vec![xnone()],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
@@ -464,9 +754,10 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords: vec![xnone()],
+            ..Default::default()
         };
 
         let eliza = Eliza::new(rules);
-        assert!(vec!["hello", "world"].contains(&eliza.greeting()));
+        assert!(["hello", "world"].contains(&eliza.greeting()));
     }
 }
@@ -0,0 +1,298 @@
+use crate::eliza::Eliza;
+use crate::rules::{Example, Rules};
+use std::fmt;
+
+/// An example which is not answered the way it documents.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExampleFailure {
+    pub word: String,
+    /// The decomposition of the example, or `None` for keyword examples.
+    pub pattern: Option<String>,
+    pub input: String,
+    pub problem: ExampleProblem,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExampleProblem {
+    /// The input ends the conversation instead.
+    Quit,
+    /// No pattern matches the input, so `xnone` answers it.
+    Unmatched,
+    /// Another keyword or decomposition answers the input, usually because
+    /// its keyword has a higher weight.
+    Stolen {
+        word: String,
+        weight: u8,
+        pattern: String,
+    },
+    /// The pattern does not capture the expected groups.
+    Groups {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+}
+
+impl fmt::Display for ExampleFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "keyword '{}', pattern '{}': ", self.word, pattern)?,
+            None => write!(f, "keyword '{}': ", self.word)?,
+        }
+
+        match &self.problem {
+            ExampleProblem::Quit => write!(f, "example '{}' is a quit word", self.input),
+            ExampleProblem::Unmatched => write!(f, "no pattern matches example '{}'", self.input),
+            ExampleProblem::Stolen {
+                word,
+                weight,
+                pattern,
+            } => write!(
+                f,
+                "example '{}' is answered by keyword '{}' (weight {}), pattern '{}'",
+                self.input, word, weight, pattern
+            ),
+            ExampleProblem::Groups { expected, actual } => write!(
+                f,
+                "example '{}' captures {:?} instead of {:?}",
+                self.input, actual, expected
+            ),
+        }
+    }
+}
+
+impl Rules {
+    /// Checks that the examples of each keyword are answered by that keyword,
+    /// and those of each decomposition by that decomposition, capturing the
+    /// expected groups if given.
+    pub fn check_examples(&self) -> Vec<ExampleFailure> {
+        let eliza = Eliza::new(self.clone());
+        let mut failures = Vec::new();
+
+        for (k, keyword) in self.keywords.iter().enumerate() {
+            for example in &keyword.examples {
+                let problem = check(self, &eliza, example, |routed, _| routed == k);
+                failures.extend(problem.map(|problem| ExampleFailure {
+                    word: keyword.word.clone(),
+                    pattern: None,
+                    input: example.input().to_string(),
+                    problem,
+                }));
+            }
+
+            for (d, decomposition) in keyword.decomposition.iter().enumerate() {
+                for example in &decomposition.examples {
+                    let problem = check(self, &eliza, example, |routed, routed_decomposition| {
+                        (routed, routed_decomposition) == (k, d)
+                    });
+                    failures.extend(problem.map(|problem| ExampleFailure {
+                        word: keyword.word.clone(),
+                        pattern: Some(decomposition.pattern.clone()),
+                        input: example.input().to_string(),
+                        problem,
+                    }));
+                }
+            }
+        }
+
+        failures
+    }
+}
+
+/// Routes an example with Eliza, built from `rules`, and tells what is wrong
+/// if it is not answered by the expected keyword and decomposition, given by
+/// their indexes in `rules`.
+fn check<F>(rules: &Rules, eliza: &Eliza, example: &Example, expected: F) -> Option<ExampleProblem>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let input = example.input();
+
+    if rules.quit.contains(&input.trim().to_lowercase()) {
+        return Some(ExampleProblem::Quit);
+    }
+
+    let route = match eliza.route(input) {
+        Some(route) => route,
+        None => return Some(ExampleProblem::Unmatched),
+    };
+
+    if !expected(route.keyword, route.decomposition) {
+        let keyword = &rules.keywords[route.keyword];
+
+        return Some(ExampleProblem::Stolen {
+            word: keyword.word.clone(),
+            weight: keyword.weight,
+            pattern: keyword.decomposition[route.decomposition].pattern.clone(),
+        });
+    }
+
+    match example.groups() {
+        Some(expected) if expected != route.groups.as_slice() => Some(ExampleProblem::Groups {
+            expected: expected.to_vec(),
+            actual: route.groups,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Rules {
+        json.parse().unwrap()
+    }
+
+    #[test]
+    fn test_check_examples_therapist() {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        assert_eq!(Vec::<ExampleFailure>::new(), rules.check_examples());
+    }
+
+    #[test]
+    fn test_check_examples() {
+        let rules = parse(
+            r#"{
+                "version": 2,
+                "initial": ["hi"],
+                "final": ["bye"],
+                "quit": ["bye"],
+                "key": [
+                    {
+                        "word": "xnone",
+                        "weight": 0,
+                        "decomp": [{ "pattern": "*", "reasmb": ["go on"] }]
+                    },
+                    {
+                        "word": "computer",
+                        "weight": 50,
+                        "decomp": [{ "pattern": "*", "reasmb": ["computers?"] }]
+                    },
+                    {
+                        "word": "dream",
+                        "weight": 3,
+                        "examples": ["i had a dream", "bye"],
+                        "decomp": [
+                            {
+                                "pattern": "* dream about *",
+                                "reasmb": ["(2)?"],
+                                "examples": [
+                                    { "input": "I dream about cats", "groups": ["i", "cats"] },
+                                    { "input": "I dream about dogs", "groups": ["i", "cats"] }
+                                ]
+                            },
+                            {
+                                "pattern": "*",
+                                "reasmb": ["dreams?"],
+                                "examples": [
+                                    "I dream about my computer",
+                                    "I dream about you",
+                                    "hello"
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        let failure = |pattern: &str, input: &str, problem| ExampleFailure {
+            word: "dream".to_string(),
+            pattern: Some(pattern.to_string()).filter(|p| !p.is_empty()),
+            input: input.to_string(),
+            problem,
+        };
+
+        let expected = vec![
+            failure("", "bye", ExampleProblem::Quit),
+            failure(
+                "* dream about *",
+                "I dream about dogs",
+                ExampleProblem::Groups {
+                    expected: vec!["i".to_string(), "cats".to_string()],
+                    actual: vec!["i".to_string(), "dogs".to_string()],
+                },
+            ),
+            failure(
+                "*",
+                "I dream about my computer",
+                ExampleProblem::Stolen {
+                    word: "computer".to_string(),
+                    weight: 50,
+                    pattern: "*".to_string(),
+                },
+            ),
+            failure(
+                "*",
+                "I dream about you",
+                ExampleProblem::Stolen {
+                    word: "dream".to_string(),
+                    weight: 3,
+                    pattern: "* dream about *".to_string(),
+                },
+            ),
+            failure("*", "hello", ExampleProblem::Unmatched),
+        ];
+
+        assert_eq!(expected, rules.check_examples());
+    }
+
+    #[test]
+    fn test_check_examples_same_pattern() {
+        let rules = parse(
+            r#"{
+                "version": 2,
+                "initial": ["hi"],
+                "final": ["bye"],
+                "quit": ["bye"],
+                "key": [
+                    {
+                        "word": "xnone",
+                        "weight": 0,
+                        "decomp": [{ "pattern": "*", "reasmb": ["go on"] }]
+                    },
+                    {
+                        "word": "dream",
+                        "weight": 3,
+                        "decomp": [
+                            { "pattern": "*", "reasmb": ["dreams?"], "examples": ["a dream"] },
+                            { "pattern": "*", "reasmb": ["never used"], "examples": ["my dream"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        let expected = vec![ExampleFailure {
+            word: "dream".to_string(),
+            pattern: Some("*".to_string()),
+            input: "my dream".to_string(),
+            problem: ExampleProblem::Stolen {
+                word: "dream".to_string(),
+                weight: 3,
+                pattern: "*".to_string(),
+            },
+        }];
+
+        assert_eq!(expected, rules.check_examples());
+    }
+
+    #[test]
+    fn test_display() {
+        let failure = ExampleFailure {
+            word: "dream".to_string(),
+            pattern: Some("*".to_string()),
+            input: "my computer dreams".to_string(),
+            problem: ExampleProblem::Stolen {
+                word: "computer".to_string(),
+                weight: 50,
+                pattern: "*".to_string(),
+            },
+        };
+
+        assert_eq!(
+            "keyword 'dream', pattern '*': example 'my computer dreams' is answered by keyword 'computer' (weight 50), pattern '*'",
+            failure.to_string()
+        );
+    }
+}
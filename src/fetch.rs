@@ -0,0 +1,508 @@
+use crate::rules::{ParseError, Rules};
+use crate::validation::ValidationError;
+use reqwest::header::LOCATION;
+use reqwest::{redirect, Client, StatusCode, Url};
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// Hosts of the files attached to Discord messages.
+const DISCORD_HOSTS: [&str; 2] = ["cdn.discordapp.com", "media.discordapp.net"];
+
+/// Number of redirects followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Downloads rules from a URL given by a user, with limits on what is fetched.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    /// Maximum size of the body, in bytes.
+    pub max_size: u64,
+    pub timeout: Duration,
+    /// Hosts rules may be fetched from, or `None` to allow every host whose
+    /// addresses are public.
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Fetcher {
+            max_size: 1024 * 1024,
+            timeout: Duration::from_secs(10),
+            allowed_hosts: None,
+        }
+    }
+}
+
+impl Fetcher {
+    /// Reads the limits from `ELIZA_FETCH_MAX_SIZE` (in bytes),
+    /// `ELIZA_FETCH_TIMEOUT` (in seconds) and `ELIZA_FETCH_ALLOWED_HOSTS`
+    /// (comma-separated), using the defaults for the missing ones.
+    pub fn from_env() -> Result<Self, String> {
+        let mut fetcher = Fetcher::default();
+
+        if let Ok(max_size) = env::var("ELIZA_FETCH_MAX_SIZE") {
+            fetcher.max_size = max_size
+                .parse()
+                .map_err(|_| format!("invalid ELIZA_FETCH_MAX_SIZE: '{}'", max_size))?;
+        }
+
+        if let Ok(timeout) = env::var("ELIZA_FETCH_TIMEOUT") {
+            let seconds = timeout
+                .parse()
+                .map_err(|_| format!("invalid ELIZA_FETCH_TIMEOUT: '{}'", timeout))?;
+            fetcher.timeout = Duration::from_secs(seconds);
+        }
+
+        if let Ok(hosts) = env::var("ELIZA_FETCH_ALLOWED_HOSTS") {
+            let hosts = hosts
+                .split(',')
+                .map(|h| h.trim().to_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect();
+            fetcher.allowed_hosts = Some(hosts);
+        }
+
+        Ok(fetcher)
+    }
+
+    /// Downloads rules, and checks that they can be used by Eliza.
+    pub async fn fetch(&self, url: &str) -> Result<Rules, FetchError> {
+        let body = self.fetch_text(url).await?;
+        parse(&body)
+    }
+
+    /// Downloads rules attached to a Discord message, like `Fetcher::fetch`.
+    /// They may only be hosted by Discord.
+    pub async fn fetch_attachment(&self, file_name: &str, url: &str) -> Result<Rules, FetchError> {
+        if !file_name.ends_with(".json") {
+            return Err(FetchError::Format(file_name.to_string()));
+        }
+
+        let fetcher = Fetcher {
+            allowed_hosts: Some(DISCORD_HOSTS.iter().map(|h| h.to_string()).collect()),
+            ..self.clone()
+        };
+
+        fetcher.fetch(url).await
+    }
+
+    async fn fetch_text(&self, url: &str) -> Result<String, FetchError> {
+        let mut url = Url::parse(url).map_err(|_| FetchError::InvalidUrl(url.to_string()))?;
+
+        // Redirects are followed here, as checking their hosts may need to
+        // resolve them.
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .redirect(redirect::Policy::none())
+            .build()
+            .map_err(FetchError::Request)?;
+
+        let mut redirects = 0;
+        let mut response = loop {
+            self.check_url(&url).await?;
+            let response = client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(FetchError::Request)?;
+
+            if !response.status().is_redirection() {
+                break response;
+            }
+
+            let location = match response.headers().get(LOCATION) {
+                Some(location) => location.to_str().unwrap_or(""),
+                None => break response,
+            };
+
+            if redirects == MAX_REDIRECTS {
+                return Err(FetchError::Redirects);
+            }
+
+            url = url
+                .join(location)
+                .map_err(|_| FetchError::InvalidUrl(location.to_string()))?;
+            redirects += 1;
+        };
+
+        if !response.status().is_success() {
+            return Err(FetchError::Status(response.status()));
+        }
+
+        if response.content_length().unwrap_or(0) > self.max_size {
+            return Err(FetchError::TooLarge(self.max_size));
+        }
+
+        // The announced length may be missing, or wrong.
+        let mut body = Vec::new();
+
+        while let Some(chunk) = response.chunk().await.map_err(FetchError::Request)? {
+            body.extend_from_slice(&chunk);
+
+            if body.len() as u64 > self.max_size {
+                return Err(FetchError::TooLarge(self.max_size));
+            }
+        }
+
+        String::from_utf8(body).map_err(|_| FetchError::Encoding)
+    }
+
+    async fn check_url(&self, url: &Url) -> Result<(), FetchError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(FetchError::Scheme(url.scheme().to_string()));
+        }
+
+        let host = url.host_str().unwrap_or("").to_lowercase();
+
+        match &self.allowed_hosts {
+            Some(hosts) if hosts.contains(&host) => return Ok(()),
+            Some(_) => return Err(FetchError::Host(host)),
+            None => {}
+        }
+
+        // Any host is allowed, except the ones of this machine or of its
+        // networks, such as cloud metadata services. The request resolves
+        // the host again, so list the hosts if it may change its addresses.
+        let addresses = match host.trim_matches(|c| c == '[' || c == ']').parse() {
+            Ok(address) => vec![address],
+            Err(_) => resolve(&host, url.port_or_known_default().unwrap_or(80)).await?,
+        };
+
+        if addresses.into_iter().all(is_public) {
+            Ok(())
+        } else {
+            Err(FetchError::Host(host))
+        }
+    }
+}
+
+/// Returns the addresses of a host, without blocking the runtime.
+async fn resolve(host: &str, port: u16) -> Result<Vec<IpAddr>, FetchError> {
+    let name = host.to_string();
+    let addresses = tokio::task::spawn_blocking(move || {
+        let addresses = (name.as_str(), port).to_socket_addrs()?;
+        Ok::<_, std::io::Error>(addresses.map(|address| address.ip()).collect())
+    })
+    .await;
+
+    match addresses {
+        Ok(Ok(addresses)) => Ok(addresses),
+        _ => Err(FetchError::Resolve(host.to_string())),
+    }
+}
+
+/// Whether an address can be reached from the internet, rather than being a
+/// loopback, link-local or private one.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            let is_shared = first == 100 && second & 0xc0 == 64;
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || first == 0
+                || is_shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                let is_unique_local = first & 0xfe00 == 0xfc00;
+                let is_link_local = first & 0xffc0 == 0xfe80;
+
+                !(ip.is_loopback() || ip.is_unspecified() || is_unique_local || is_link_local)
+            }
+        },
+    }
+}
+
+/// Parses downloaded rules, and checks that they can be used by Eliza.
+fn parse(body: &str) -> Result<Rules, FetchError> {
+    let rules: Rules = body.parse().map_err(FetchError::Parse)?;
+
+    if !rules.extends.is_empty() {
+        return Err(FetchError::Extends);
+    }
+
+    rules.validate().map_err(FetchError::Invalid)?;
+    Ok(rules)
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Format(String),
+    InvalidUrl(String),
+    Scheme(String),
+    Host(String),
+    Resolve(String),
+    Redirects,
+    Request(reqwest::Error),
+    Status(StatusCode),
+    TooLarge(u64),
+    Encoding,
+    Parse(ParseError),
+    Extends,
+    Invalid(Vec<ValidationError>),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Format(file_name) => {
+                write!(f, "{} is not a supported rule file (.json).", file_name)
+            }
+            FetchError::InvalidUrl(url) => write!(f, "'{}' is not a valid URL.", url),
+            FetchError::Scheme(scheme) => {
+                write!(f, "Only http and https URLs are allowed, not {}.", scheme)
+            }
+            FetchError::Host(host) => write!(f, "Rules cannot be loaded from {}.", host),
+            FetchError::Resolve(host) => write!(f, "Could not find the address of {}.", host),
+            FetchError::Redirects => write!(f, "The rules were redirected too many times."),
+            FetchError::Request(e) if e.is_timeout() => write!(f, "The request timed out."),
+            FetchError::Request(e) => write!(f, "The request failed: {}", e),
+            FetchError::Status(status) => write!(f, "The server answered {}.", status),
+            FetchError::TooLarge(max_size) => {
+                write!(f, "The rules are larger than {} bytes.", max_size)
+            }
+            FetchError::Encoding => write!(f, "The rules are not valid UTF-8."),
+            FetchError::Parse(e) => write!(f, "Invalid rules: {}", e),
+            FetchError::Extends => write!(f, "Remote rules cannot extend other rule files."),
+            FetchError::Invalid(errors) => {
+                write!(f, "Invalid rules:")?;
+
+                for error in errors {
+                    write!(f, "\n- {}", error)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tokio::runtime::Runtime;
+
+    fn fetch(fetcher: &Fetcher, url: &str) -> Result<Rules, FetchError> {
+        Runtime::new().unwrap().block_on(fetcher.fetch(url))
+    }
+
+    fn fetch_attachment(fetcher: &Fetcher, name: &str, url: &str) -> Result<Rules, FetchError> {
+        Runtime::new()
+            .unwrap()
+            .block_on(fetcher.fetch_attachment(name, url))
+    }
+
+    /// Serves a single HTTP request with the given raw response, after the
+    /// given delay, and returns the URL of the server.
+    fn stub(response: String, delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+
+            while reader.read_line(&mut line).is_ok() && line != "\r\n" {
+                line.clear();
+            }
+
+            thread::sleep(delay);
+            let _ = stream.write_all(response.as_bytes());
+        });
+
+        format!("http://{}/rules.json", address)
+    }
+
+    fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    fn therapist() -> String {
+        fs::read_to_string("rules/therapist.json").unwrap()
+    }
+
+    /// A fetcher allowed to reach the stubs, which private addresses are not.
+    fn local() -> Fetcher {
+        Fetcher {
+            allowed_hosts: Some(vec!["127.0.0.1".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fetch() {
+        let url = stub(ok(&therapist()), Duration::from_secs(0));
+        assert!(fetch(&local(), &url).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_redirect() {
+        let url = stub(ok(&therapist()), Duration::from_secs(0));
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+            url
+        );
+        let url = stub(response, Duration::from_secs(0));
+
+        assert!(fetch(&local(), &url).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_private_addresses() {
+        let fetcher = Fetcher::default();
+
+        for url in &[
+            "http://127.0.0.1/rules.json",
+            "http://localhost:8080/rules.json",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/rules.json",
+            "http://192.168.1.1/rules.json",
+            "http://[::1]/rules.json",
+            "http://[::ffff:127.0.0.1]/rules.json",
+        ] {
+            match fetch(&fetcher, url) {
+                Err(FetchError::Host(_)) => {}
+                other => panic!("expected a forbidden host for {}, got {:?}", url, other),
+            }
+        }
+
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert!(is_public("2606:2800:220:1::".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_fetch_invalid_url() {
+        let fetcher = Fetcher::default();
+
+        match fetch(&fetcher, "not a url") {
+            Err(FetchError::InvalidUrl(_)) => {}
+            other => panic!("expected an invalid URL, got {:?}", other),
+        }
+
+        match fetch(&fetcher, "file:///etc/passwd") {
+            Err(FetchError::Scheme(scheme)) => assert_eq!("file", scheme),
+            other => panic!("expected an invalid scheme, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_allowed_hosts() {
+        let fetcher = Fetcher {
+            allowed_hosts: Some(vec!["example.com".to_string()]),
+            ..Default::default()
+        };
+
+        match fetch(&fetcher, "http://127.0.0.1/rules.json") {
+            Err(FetchError::Host(host)) => assert_eq!("127.0.0.1", host),
+            other => panic!("expected a forbidden host, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_attachment() {
+        let fetcher = Fetcher::default();
+
+        match fetch_attachment(
+            &fetcher,
+            "rules.txt",
+            "https://cdn.discordapp.com/rules.txt",
+        ) {
+            Err(FetchError::Format(file_name)) => assert_eq!("rules.txt", file_name),
+            other => panic!("expected an unsupported format, got {:?}", other),
+        }
+
+        match fetch_attachment(&fetcher, "rules.json", "http://127.0.0.1/rules.json") {
+            Err(FetchError::Host(host)) => assert_eq!("127.0.0.1", host),
+            other => panic!("expected a forbidden host, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_status() {
+        let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string();
+        let url = stub(response, Duration::from_secs(0));
+
+        match fetch(&local(), &url) {
+            Err(FetchError::Status(status)) => assert_eq!(StatusCode::NOT_FOUND, status),
+            other => panic!("expected an error status, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_too_large() {
+        let fetcher = Fetcher {
+            max_size: 100,
+            ..local()
+        };
+
+        let url = stub(ok(&therapist()), Duration::from_secs(0));
+
+        match fetch(&fetcher, &url) {
+            Err(FetchError::TooLarge(100)) => {}
+            other => panic!("expected too large rules, got {:?}", other),
+        }
+
+        // Without a length, the body is cut while being read.
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{}",
+            therapist()
+        );
+        let url = stub(response, Duration::from_secs(0));
+
+        match fetch(&fetcher, &url) {
+            Err(FetchError::TooLarge(100)) => {}
+            other => panic!("expected too large rules, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_timeout() {
+        let fetcher = Fetcher {
+            timeout: Duration::from_millis(100),
+            ..local()
+        };
+
+        let url = stub(ok(&therapist()), Duration::from_secs(2));
+
+        match fetch(&fetcher, &url) {
+            Err(FetchError::Request(e)) => assert!(e.is_timeout()),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fetch_invalid_rules() {
+        let url = stub(ok("<html></html>"), Duration::from_secs(0));
+
+        match fetch(&local(), &url) {
+            Err(FetchError::Parse(_)) => {}
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let url = stub(ok("{}"), Duration::from_secs(0));
+
+        match fetch(&local(), &url) {
+            Err(FetchError::Invalid(errors)) => assert_eq!(3, errors.len()),
+            other => panic!("expected invalid rules, got {:?}", other),
+        }
+    }
+}
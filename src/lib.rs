@@ -0,0 +1,42 @@
+//! A (toy) implementation of Eliza: the engine answering messages with the
+//! keywords, patterns and templates of a rule file.
+//!
+//! ```
+//! use eliza::{Eliza, Rules};
+//!
+//! let (rules, _warnings) = Rules::load("rules/therapist.json").unwrap();
+//! let mut eliza = Eliza::new(rules);
+//! eliza.seed(1);
+//!
+//! println!("{}", eliza.greeting());
+//!
+//! let response = eliza.interact("I remember my mother");
+//! assert_eq!("Do you often think of your mother ?", response.message);
+//! assert!(eliza.interact("bye").is_farewell);
+//! ```
+//!
+//! The engine only depends on serde and rand. The `remote-rules` feature adds
+//! `fetch`, to download rules over HTTP. The terminal frontend is the `eliza`
+//! binary, built with the `cli` feature; the `discord` feature adds the
+//! discord bot to it, and `http` a JSON API.
+
+pub mod chat;
+pub mod coverage;
+pub mod diff;
+pub mod eliza;
+pub mod examples;
+#[cfg(feature = "remote-rules")]
+pub mod fetch;
+pub mod pattern;
+pub mod registry;
+pub mod rules;
+pub mod schema;
+#[cfg(test)]
+mod testing;
+pub mod transcript;
+pub mod validation;
+
+pub use crate::eliza::{Eliza, Response, Route, Trace};
+pub use pattern::match_pattern;
+pub use registry::ScriptRegistry;
+pub use rules::Rules;
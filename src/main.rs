@@ -1,108 +1,514 @@
-use discord::Handler;
-use eliza::Eliza;
-use rules::Rules;
-use serenity::prelude::*;
+use cli::{Command, Opt, ScriptOptions};
+use eliza::chat::{self, Sessions};
+use eliza::coverage::Coverage;
+use eliza::schema;
+use eliza::transcript::{self, Transcript};
+use eliza::{Rules, ScriptRegistry};
+use repl::{Repl, ReplayTransport};
 use std::{
-    env, fs,
-    io::{self, ErrorKind, Write},
+    env,
+    fmt::Display,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
     process,
-    sync::Mutex,
 };
+use structopt::StructOpt;
 
+mod batch;
+mod cli;
+#[cfg(feature = "discord")]
+mod commands;
+#[cfg(feature = "discord")]
 mod discord;
-mod eliza;
-mod pattern;
-mod rules;
+#[cfg(feature = "discord")]
+mod permissions;
+#[cfg(feature = "discord")]
+mod ratelimit;
+mod repl;
+#[cfg(feature = "discord")]
+mod rulesets;
+#[cfg(feature = "http")]
+mod serve;
+#[cfg(feature = "discord")]
+mod slash;
+#[cfg(feature = "discord")]
+mod store;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "discord")]
+mod triggers;
+#[cfg(feature = "discord")]
+mod watch;
 
 fn main() -> io::Result<()> {
-    dotenv::dotenv().ok();
+    let opt = Opt::from_args();
 
-    let args: Vec<String> = env::args().collect();
+    match &opt.config {
+        Some(path) => {
+            if let Err(err) = dotenv::from_path(path) {
+                eprintln!("Error: could not read '{}': {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+        None => {
+            dotenv::dotenv().ok();
+        }
+    }
+
+    match opt.command {
+        Command::Chat { rules, options } => chat(&rules, &options),
+        #[cfg(feature = "discord")]
+        Command::Discord { storage } => discord(storage),
+        Command::Check { paths } => check(&paths),
+        Command::Convert { input, output } => convert(&input, output.as_deref()),
+        Command::Explain {
+            rules,
+            message,
+            options,
+        } => {
+            let (scripts, current) = load_scripts(&rules, &options);
+            println!("{}", scripts.get(&current).unwrap().trace(&message));
+            Ok(())
+        }
+        Command::Replay {
+            rules,
+            input,
+            options,
+        } => replay(&rules, input.as_deref(), &options),
+        Command::Batch {
+            rules,
+            input,
+            options,
+        } => {
+            let (scripts, current) = load_scripts(&rules, &options);
+            let eliza = scripts.get(&current).unwrap();
+            let stdout = io::stdout();
+
+            match input {
+                Some(input) => {
+                    let input = io::BufReader::new(fs::File::open(input)?);
+                    batch::run(eliza, input, stdout.lock(), options.trace)
+                }
+                None => batch::run(eliza, io::stdin().lock(), stdout.lock(), options.trace),
+            }
+        }
+        Command::Coverage {
+            rules,
+            input,
+            options,
+        } => coverage(&rules, input.as_deref(), &options),
+        Command::Test { paths } => test(&paths),
+        #[cfg(feature = "http")]
+        Command::Serve {
+            rules,
+            address,
+            options,
+        } => {
+            let (scripts, _) = load_scripts(&rules, &options);
+            serve::Server::new(scripts, options.seed, options.trace).listen(&address)
+        }
+        Command::Schema { output } => {
+            let schema = serde_json::to_string_pretty(&schema::json_schema())?;
+            fs::write(output, schema + "\n")
+        }
+    }
+}
+
+/// Runs the discord bot, storing the rules it loads in `storage`.
+#[cfg(feature = "discord")]
+fn discord(storage: PathBuf) -> io::Result<()> {
+    use arc_swap::ArcSwap;
+    use commands::CommandParser;
+    use discord::{Bot, Handler, CONVERSATION_TIMEOUT};
+    use eliza::fetch::Fetcher;
+    use permissions::Permissions;
+    use ratelimit::{Limits, RateLimiter};
+    use rulesets::{Rulesets, Scope};
+    use serenity::prelude::*;
+    use slash::SlashClient;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::runtime;
+    use triggers::Triggers;
+
+    let store_kind = env::var("ELIZA_STORE").unwrap_or_else(|_| "file".to_string());
+
+    if store_kind == "file" && storage.is_file() {
+        eprintln!(
+            "Error: '{}' should be a directory. Move the rules file to '{}' in a new directory.",
+            storage.display(),
+            Scope::Global.file_name()
+        );
+        process::exit(2);
+    }
 
-    let mode = match args.len() {
-        3 => &args[1],
-        _ => {
-            eprintln!("Usage: {} cli [rules-path]", args[0]);
-            eprintln!("Usage: {} discord [rule-storage-file]", args[0]);
+    let store = match store::open(&store_kind, &storage) {
+        Ok(store) => store,
+        Err(err) => {
+            eprintln!("Error: {}", err);
             process::exit(1);
         }
     };
 
-    if mode == "discord" {
-        let rule_storage_file = args[2].to_string();
+    let (rulesets, warnings) = match Rulesets::load_store(store.as_ref()) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("Error: could not load the stored rules: {}", err);
+            process::exit(2);
+        }
+    };
+    print_warnings(&warnings);
 
-        let eliza = match fs::read_to_string(&rule_storage_file) {
-            Ok(string) => Some(Eliza::new(
-                serde_json::from_str(&string).expect("could not parse JSON file"),
-            )),
-            Err(err) if err.kind() == ErrorKind::NotFound => None,
-            Err(err) => {
-                eprintln!("Failed to read file '{}'.", rule_storage_file);
-                eprintln!("Error: {}", err);
-                process::exit(2);
+    let rulesets = Arc::new(ArcSwap::from_pointee(rulesets));
+
+    // Files may also be edited by hand.
+    if store_kind == "file" {
+        let rulesets = Arc::clone(&rulesets);
+        let dir = storage.clone();
+
+        watch::watch(dir.clone(), Duration::from_secs(2), move || {
+            // Scopes whose files were removed are dropped, and those whose
+            // files are invalid keep their current rules. Rules loaded by a
+            // command in the meantime are also saved to the directory, so
+            // the next change picks them up if this swap replaces them.
+            match rulesets.load().reload_dir(&dir) {
+                Ok((reloaded, warnings)) => {
+                    rulesets.store(Arc::new(reloaded));
+                    print_warnings(&warnings);
+                    println!("Reloaded rules from '{}'.", dir.display());
+                }
+                Err(err) => eprintln!("Keeping the current rules: {}", err),
             }
-        };
-
-        let handler = Handler {
-            eliza: Mutex::new(eliza),
-            store: Box::new(move |rules| match serde_json::to_string(rules) {
-                // TODO: map
-                Ok(serialized) => match fs::write(&rule_storage_file, serialized) {
-                    Ok(_) => true,
-                    Err(e) => {
-                        eprintln!("Could not serialize : {}", e);
-                        false
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Could not serialize : {}", e);
-                    false
+        });
+    }
+
+    let mut scripts = match env::var("ELIZA_SCRIPTS_DIR") {
+        Ok(dir) => {
+            let (scripts, warnings) = ScriptRegistry::load(dir)?;
+            print_warnings(&warnings);
+            scripts
+        }
+        Err(_) => ScriptRegistry::new(),
+    };
+
+    set_default_script(&mut scripts);
+
+    let permissions = match Permissions::from_env() {
+        Ok(permissions) => permissions,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let triggers = match Triggers::from_env() {
+        Ok(triggers) => triggers,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let limits = match Limits::from_env() {
+        Ok(limits) => limits,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let commands = match CommandParser::from_env() {
+        Ok(commands) => commands,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let fetcher = match Fetcher::from_env() {
+        Ok(fetcher) => fetcher,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+
+    let bot_id = env::var("DISCORD_BOT_ID")
+        .expect("Expected the bot id in the environment")
+        .parse()
+        .expect("The specified bot id is not a valid number.");
+
+    let slash_guild = env::var("ELIZA_SLASH_GUILD").ok().map(|id| {
+        id.parse()
+            .expect("The specified slash command guild is not a valid id.")
+    });
+
+    let bot = Bot {
+        triggers,
+        commands,
+        permissions,
+        fetcher,
+        limiter: RateLimiter::new(limits),
+        rulesets,
+        scripts,
+        selections: Mutex::new(HashMap::new()),
+        sessions: Sessions::with_idle_timeout(CONVERSATION_TIMEOUT),
+        store,
+        slash: SlashClient::new(&token, bot_id),
+        slash_guild,
+        bot_id,
+    };
+
+    let runtime = runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()?;
+
+    let handler = Handler {
+        bot: Arc::new(bot),
+        runtime: runtime.handle().clone(),
+    };
+
+    let mut client = Client::new(&token, handler).expect("Could not create serenity client");
+
+    if let Err(why) = client.start() {
+        println!("Client error: {:?}", why);
+    }
+
+    Ok(())
+}
+
+/// Chats with a script in the terminal, see `repl::Repl`.
+fn chat(path: &Path, options: &ScriptOptions) -> io::Result<()> {
+    let (scripts, current) = load_scripts(path, options);
+    Repl::new(path, scripts, current, options).run()
+}
+
+/// Replays the messages of a file, or of the standard input, one per line.
+fn replay(path: &Path, input: Option<&Path>, options: &ScriptOptions) -> io::Result<()> {
+    let (scripts, current) = load_scripts(path, options);
+    let mut transport = ReplayTransport::new(read_lines(input)?, options.trace);
+    chat::run(
+        scripts.get(&current).unwrap(),
+        &Sessions::new(),
+        &mut transport,
+    )
+}
+
+/// Answers the messages of a file, or of the standard input, and prints how
+/// often each rule was used.
+fn coverage(path: &Path, input: Option<&Path>, options: &ScriptOptions) -> io::Result<()> {
+    let (scripts, current) = load_scripts(path, options);
+    let eliza = scripts.get(&current).unwrap();
+    let mut coverage = Coverage::new(eliza.rules());
+
+    for line in read_lines(input)? {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let trace = eliza.trace(line);
+
+        if options.trace {
+            eprintln!("> {}", line);
+
+            for line in trace.to_string().lines() {
+                eprintln!("  | {}", line);
+            }
+        }
+
+        coverage.record(line, &trace);
+    }
+
+    print!("{}", coverage);
+    Ok(())
+}
+
+/// Reads the lines of a file, or of the standard input.
+fn read_lines(input: Option<&Path>) -> io::Result<Vec<String>> {
+    match input {
+        Some(input) => Ok(fs::read_to_string(input)?
+            .lines()
+            .map(str::to_string)
+            .collect()),
+        None => io::stdin().lock().lines().collect(),
+    }
+}
+
+/// Checks rule files, or the rule files of directories, and their examples.
+/// Exits with an error if one of them is invalid.
+fn check(paths: &[PathBuf]) -> io::Result<()> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let file = entry?.path();
+                let is_json = file.extension() == Some("json".as_ref());
+                let is_schema = file.file_name() == Some(schema::SCHEMA_FILE.as_ref());
+
+                if is_json && !is_schema {
+                    files.push(file);
                 }
-            }),
-            bot_id: env::var("DISCORD_BOT_ID")
-                .expect("Expected the bot id in the environment")
-                .parse()
-                .expect("The specified bot id is not a valid number."),
-        };
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
 
-        let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
-        let mut client = Client::new(&token, handler).expect("Could not create serenity client");
+    files.sort();
+    let mut invalid = 0;
 
-        if let Err(why) = client.start() {
-            println!("Client error: {:?}", why);
+    for file in &files {
+        match Rules::load(file) {
+            Ok((rules, warnings)) => {
+                let failures = rules.check_examples();
+
+                if failures.is_empty() {
+                    println!("ok: {}", file.display());
+                } else {
+                    for failure in &failures {
+                        eprintln!("Error: {}: {}", file.display(), failure);
+                    }
+
+                    invalid += 1;
+                }
+
+                print_warnings(&warnings);
+                print_warnings(&rules.warnings());
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                invalid += 1;
+            }
         }
-    } else if mode == "cli" {
-        let filename = &args[2];
+    }
 
-        let rule_contents = fs::read_to_string(&filename)?;
-        let rules: Rules =
-            serde_json::from_str(&rule_contents).expect("Could not parse JSON file.");
-        let eliza = Eliza::new(rules);
+    if invalid > 0 {
+        eprintln!("{} of {} rule files are invalid.", invalid, files.len());
+        process::exit(1);
+    }
 
-        println!("{}", eliza.greeting());
+    Ok(())
+}
 
-        while let Some(input) = prompt("> ")? {
-            let response = eliza.interact(&input);
-            println!("{}", response.message);
+/// Plays the transcripts of files or directories, printing the replies which
+/// differ. Exits with an error if there are any.
+fn test(paths: &[PathBuf]) -> io::Result<()> {
+    let files = transcript::find(paths)?;
+    let mut failed = 0;
 
-            if response.is_farewell {
-                break;
+    for file in &files {
+        match Transcript::load(file).and_then(|transcript| transcript.run()) {
+            Ok(mismatches) if mismatches.is_empty() => println!("ok: {}", file.display()),
+            Ok(mismatches) => {
+                println!("FAILED: {}", file.display());
+
+                for mismatch in mismatches {
+                    println!("{}", mismatch);
+                }
+
+                failed += 1;
+            }
+            Err(err) => {
+                println!("FAILED: {}: {}", file.display(), err);
+                failed += 1;
             }
         }
     }
 
+    println!("{} passed, {} failed.", files.len() - failed, failed);
+
+    if failed > 0 {
+        process::exit(1);
+    }
+
     Ok(())
 }
 
-/// Prompts the user for a line of text, and returns it.
-fn prompt(prompt: &str) -> io::Result<Option<String>> {
-    print!("{}", prompt);
-    io::stdout().flush()?;
+/// Upgrades a rule file to the current version, see `schema::migrate`.
+fn convert(input: &Path, output: Option<&Path>) -> io::Result<()> {
+    let rules = match Rules::from_json(&fs::read_to_string(input)?) {
+        Ok((rules, dropped)) => {
+            for field in dropped {
+                eprintln!("Warning: dropped unknown field '{}'.", field);
+            }
+
+            rules
+        }
+        Err(err) => {
+            eprintln!("Error: could not parse '{}': {}", input.display(), err);
+            process::exit(1);
+        }
+    };
+
+    let converted = serde_json::to_string_pretty(&rules)? + "\n";
 
-    let mut input = String::new();
+    match output {
+        Some(output) => fs::write(output, converted),
+        None => io::stdout().write_all(converted.as_bytes()),
+    }
+}
 
-    match io::stdin().read_line(&mut input)? {
-        0 => Ok(None),
-        _ => Ok(Some(input.to_string())),
+/// Loads the scripts of a rule file or directory, seeded if asked for, and
+/// returns them with the name of the one to use. Exits if there is none.
+fn load_scripts(path: &Path, options: &ScriptOptions) -> (ScriptRegistry, String) {
+    let mut scripts = match ScriptRegistry::load(path) {
+        Ok((scripts, warnings)) => {
+            print_warnings(&warnings);
+            scripts
+        }
+        Err(err) => {
+            eprintln!("Error: could not read '{}': {}", path.display(), err);
+            process::exit(2);
+        }
+    };
+
+    if let Some(seed) = options.seed {
+        scripts.seed(seed);
+    }
+
+    match &options.script {
+        Some(name) => {
+            if !scripts.set_default(name) {
+                eprintln!("Error: unknown script '{}'.", name);
+                process::exit(2);
+            }
+        }
+        None => set_default_script(&mut scripts),
+    }
+
+    match scripts.default_name() {
+        Some(name) => {
+            let name = name.to_string();
+            (scripts, name)
+        }
+        None => {
+            eprintln!("Error: no rules could be loaded from '{}'.", path.display());
+            process::exit(2);
+        }
+    }
+}
+
+/// Prints the warnings raised while loading rules, such as merge conflicts.
+fn print_warnings<T: Display>(warnings: &[T]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Sets the default script to the one named by the `ELIZA_DEFAULT_SCRIPT`
+/// environment variable, if any.
+fn set_default_script(scripts: &mut ScriptRegistry) {
+    if let Ok(name) = env::var("ELIZA_DEFAULT_SCRIPT") {
+        if !scripts.set_default(&name) {
+            eprintln!("Warning: unknown default script '{}'.", name);
+        }
     }
 }
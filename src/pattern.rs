@@ -7,7 +7,7 @@ use crate::rules::Synonym;
 pub fn match_pattern<'a>(
     pattern: &str,
     input: &'a str,
-    synonyms: &Vec<Synonym>,
+    synonyms: &[Synonym],
 ) -> Option<Vec<&'a str>> {
     //pattern = pattern.trim(); // TODO: needed?
 
@@ -66,7 +66,7 @@ pub fn match_pattern<'a>(
             groups.push(&input[start_index..end_index]);
         }
 
-        if pattern_part.chars().next().unwrap() == '@' {
+        if pattern_part.starts_with('@') {
             if has_space {
                 groups.push(&input[last_index..(input_index - 1)]);
             } else {
@@ -84,9 +84,22 @@ pub fn match_pattern<'a>(
     Some(groups)
 }
 
+/// Returns the number of groups `match_pattern` returns when the pattern
+/// matches: one per star, and one per synonym.
+pub fn group_count(pattern: &str) -> usize {
+    if pattern == "*" {
+        return 1;
+    }
+
+    pattern
+        .split(' ')
+        .filter(|p| *p == "*" || p.starts_with('@'))
+        .count()
+}
+
 /// Checks if the given words matches the pattern part, that is either it is
 /// the same, or it is a synonym.
-fn is_compatible(word: &str, pattern_part: &str, synonyms: &Vec<Synonym>) -> bool {
+fn is_compatible(word: &str, pattern_part: &str, synonyms: &[Synonym]) -> bool {
     if word == pattern_part {
         true
     } else {
@@ -98,11 +111,11 @@ fn is_compatible(word: &str, pattern_part: &str, synonyms: &Vec<Synonym>) -> boo
 }
 
 /// Checks if the given word is a synonym associated with the given symbol.
-fn is_synonym(word: &str, label: &str, synonyms: &Vec<Synonym>) -> bool {
+fn is_synonym(word: &str, label: &str, synonyms: &[Synonym]) -> bool {
     let synonym = synonyms
         .iter()
         .find(|s| s.label == label)
-        .expect(&format!("unknown synonym {}", label));
+        .unwrap_or_else(|| panic!("unknown synonym {}", label));
 
     synonym.list.iter().any(|w| *w == word)
 }
@@ -116,7 +129,7 @@ mod tests {
         let input = "hello there";
         let pattern = "*";
         let expected = Some(vec!["hello there"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
     }
 
     #[test]
@@ -125,19 +138,19 @@ mod tests {
         let input = "hey i really like you";
         let pattern = "hey i * you";
         let expected = Some(vec!["really like"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
 
         // left
         let input = "really like you";
         let pattern = "* you";
         let expected = Some(vec!["really like"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
 
         // right
         let input = "i really like";
         let pattern = "i *";
         let expected = Some(vec!["really like"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
     }
 
     #[test]
@@ -145,12 +158,12 @@ mod tests {
         let input = "somehow i am really happy";
         let pattern = "* i am *";
         let expected = Some(vec!["somehow", "really happy"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
 
         let input = "how are you doing today";
         let pattern = "how * you * today";
         let expected = Some(vec!["are", "doing"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &vec![]));
+        assert_eq!(expected, match_pattern(pattern, input, &[]));
     }
 
     #[test]
@@ -167,7 +180,7 @@ mod tests {
         let input = "better";
         let pattern = "@happy";
         let expected = Some(vec!["better"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &synonyms));
+        assert_eq!(expected, match_pattern(pattern, input, &synonyms));
     }
 
     #[test]
@@ -185,7 +198,15 @@ mod tests {
         let input = "it really was like hell";
         let pattern = "* @be * like *";
         let expected = Some(vec!["it really", "was", "", "hell"]);
-        assert_eq!(expected, match_pattern(pattern, &input, &synonyms));
+        assert_eq!(expected, match_pattern(pattern, input, &synonyms));
+    }
+
+    #[test]
+    fn test_group_count() {
+        assert_eq!(1, group_count("*"));
+        assert_eq!(0, group_count("hello"));
+        assert_eq!(2, group_count("* i am *"));
+        assert_eq!(4, group_count("* @be * like *"));
     }
 
     #[test]
@@ -207,13 +228,13 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_is_synonym_unknown_label() {
-        is_synonym("happy", "@happy", &vec![]);
+        is_synonym("happy", "@happy", &[]);
     }
 
     #[test]
     fn test_is_compatible_equals() {
-        assert!(is_compatible("happy", "happy", &vec![]));
-        assert!(!is_compatible("sad", "happy", &vec![]));
+        assert!(is_compatible("happy", "happy", &[]));
+        assert!(!is_compatible("sad", "happy", &[]));
     }
 
     #[test]
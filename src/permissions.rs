@@ -0,0 +1,125 @@
+use crate::rulesets::Scope;
+use std::env;
+
+/// Who may run the admin commands of the discord bot, such as `!load_rules`.
+#[derive(Debug, Default)]
+pub struct Permissions {
+    /// Users allowed everywhere, including to change the global rules.
+    pub admin_users: Vec<u64>,
+    /// Roles allowed in the guilds they belong to.
+    pub admin_roles: Vec<u64>,
+}
+
+/// What is known about the author of a command.
+#[derive(Debug, Default)]
+pub struct Author {
+    pub id: u64,
+    pub roles: Vec<u64>,
+    /// Whether the author has the Manage Server permission in the guild of
+    /// the message. Also true in direct messages, whose rules only concern
+    /// their author.
+    pub can_manage: bool,
+}
+
+impl Permissions {
+    /// Reads the comma-separated ids of `ELIZA_ADMIN_USERS` and
+    /// `ELIZA_ADMIN_ROLES`.
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Permissions {
+            admin_users: ids_from_env("ELIZA_ADMIN_USERS")?,
+            admin_roles: ids_from_env("ELIZA_ADMIN_ROLES")?,
+        })
+    }
+
+    /// Checks whether the author may change the rules of a scope.
+    ///
+    /// Only admin users may change the global rules. Guild and channel rules
+    /// may also be changed by the members having an admin role, or the
+    /// Manage Server permission.
+    pub fn allows(&self, author: &Author, scope: Scope) -> bool {
+        if self.admin_users.contains(&author.id) {
+            return true;
+        }
+
+        match scope {
+            Scope::Global => false,
+            Scope::Guild(_) | Scope::Channel(_) => {
+                author.can_manage || author.roles.iter().any(|r| self.admin_roles.contains(r))
+            }
+        }
+    }
+}
+
+/// Reads a comma-separated list of ids from an environment variable, empty if
+/// it is not set.
+pub fn ids_from_env(name: &str) -> Result<Vec<u64>, String> {
+    let value = match env::var(name) {
+        Ok(value) => value,
+        Err(_) => return Ok(vec![]),
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| format!("{} contains an invalid id: '{}'", name, id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions() -> Permissions {
+        Permissions {
+            admin_users: vec![1],
+            admin_roles: vec![10],
+        }
+    }
+
+    #[test]
+    fn test_admin_users_are_allowed_everywhere() {
+        let author = Author {
+            id: 1,
+            ..Default::default()
+        };
+
+        assert!(permissions().allows(&author, Scope::Global));
+        assert!(permissions().allows(&author, Scope::Guild(100)));
+    }
+
+    #[test]
+    fn test_roles_and_managers_are_allowed_in_their_guild() {
+        let with_role = Author {
+            id: 2,
+            roles: vec![11, 10],
+            can_manage: false,
+        };
+        let manager = Author {
+            id: 3,
+            roles: vec![],
+            can_manage: true,
+        };
+
+        for author in &[with_role, manager] {
+            assert!(permissions().allows(author, Scope::Guild(100)));
+            assert!(permissions().allows(author, Scope::Channel(200)));
+            assert!(!permissions().allows(author, Scope::Global));
+        }
+    }
+
+    #[test]
+    fn test_others_are_denied() {
+        let author = Author {
+            id: 4,
+            roles: vec![11],
+            can_manage: false,
+        };
+
+        assert!(!permissions().allows(&author, Scope::Guild(100)));
+        assert!(!permissions().allows(&author, Scope::Channel(200)));
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of buckets above which the ones refilled to capacity are forgotten,
+/// as a new bucket starts full anyway.
+const MAX_BUCKETS: usize = 10_000;
+
+/// At most `capacity` actions per `period`, refilled continuously.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl Limit {
+    pub fn new(capacity: u32, seconds: u64) -> Self {
+        Limit {
+            capacity,
+            period: Duration::from_secs(seconds),
+        }
+    }
+
+    /// Parses `<capacity>/<seconds>`, e.g. `5/10`, or `none` for no limit.
+    pub fn parse(s: &str) -> Option<Option<Limit>> {
+        if s.trim() == "none" {
+            return Some(None);
+        }
+
+        let mut parts = s.trim().splitn(2, '/');
+        let capacity = parts.next()?.trim().parse().ok()?;
+        let seconds = parts.next()?.trim().parse().ok()?;
+
+        if capacity == 0 || seconds == 0 {
+            return None;
+        }
+
+        Some(Some(Limit::new(capacity, seconds)))
+    }
+
+    /// Time it takes to get a token back.
+    fn refill(&self) -> Duration {
+        self.period / self.capacity
+    }
+}
+
+/// The limits of the discord bot, `None` when disabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub user: Option<Limit>,
+    pub channel: Option<Limit>,
+    pub guild: Option<Limit>,
+    /// Loading rules, per user, on top of the other limits.
+    pub load: Option<Limit>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            user: Some(Limit::new(5, 10)),
+            channel: Some(Limit::new(20, 10)),
+            guild: Some(Limit::new(60, 10)),
+            load: Some(Limit::new(2, 60)),
+        }
+    }
+}
+
+impl Limits {
+    /// Reads `ELIZA_RATE_USER`, `ELIZA_RATE_CHANNEL`, `ELIZA_RATE_GUILD` and
+    /// `ELIZA_RATE_LOAD`, see `Limit::parse`, using the defaults for the
+    /// missing ones.
+    pub fn from_env() -> Result<Self, String> {
+        let mut limits = Limits::default();
+
+        for (name, limit) in [
+            ("ELIZA_RATE_USER", &mut limits.user),
+            ("ELIZA_RATE_CHANNEL", &mut limits.channel),
+            ("ELIZA_RATE_GUILD", &mut limits.guild),
+            ("ELIZA_RATE_LOAD", &mut limits.load),
+        ] {
+            if let Ok(value) = env::var(name) {
+                *limit = Limit::parse(&value).ok_or_else(|| {
+                    format!(
+                        "invalid {}: '{}' (expected <count>/<seconds> or none)",
+                        name, value
+                    )
+                })?;
+            }
+        }
+
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    User(u64),
+    Channel(u64),
+    Guild(u64),
+    Load(u64),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Whether the cooldown message was sent since the bucket ran out.
+    warned: bool,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let rate = limit.capacity as f64 / limit.period.as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * rate).min(limit.capacity as f64);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.warned = false;
+        }
+    }
+}
+
+/// What to do with an action.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// The action is refused until `retry_after`. `warn` is only true the
+    /// first time, so that a cooldown message is not sent for every refused
+    /// action.
+    Deny {
+        retry_after: Duration,
+        warn: bool,
+    },
+}
+
+/// Token buckets per user, channel and guild.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: Limits,
+    buckets: Mutex<HashMap<Key, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks whether a user may be answered in a channel, and takes a token
+    /// from each of its buckets if so. `load` is whether rules are loaded.
+    pub fn check(&self, user: u64, channel: u64, guild: Option<u64>, load: bool) -> Decision {
+        self.check_at(user, channel, guild, load, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        user: u64,
+        channel: u64,
+        guild: Option<u64>,
+        load: bool,
+        now: Instant,
+    ) -> Decision {
+        let mut keys = vec![Key::User(user), Key::Channel(channel)];
+
+        if let Some(guild) = guild {
+            keys.push(Key::Guild(guild));
+        }
+
+        if load {
+            keys.push(Key::Load(user));
+        }
+
+        let keys: Vec<(Key, Limit)> = keys
+            .into_iter()
+            .filter_map(|key| self.limit(key).map(|limit| (key, limit)))
+            .collect();
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_BUCKETS {
+            self.prune(&mut buckets, now);
+        }
+
+        let mut retry_after = Duration::from_secs(0);
+        let mut warn = false;
+
+        for (key, limit) in &keys {
+            let bucket = buckets.entry(*key).or_insert(Bucket {
+                tokens: limit.capacity as f64,
+                updated: now,
+                warned: false,
+            });
+
+            bucket.refill(*limit, now);
+
+            if bucket.tokens < 1.0 {
+                let missing = (1.0 - bucket.tokens) * limit.refill().as_secs_f64();
+                retry_after = retry_after.max(Duration::from_secs_f64(missing));
+                warn |= !bucket.warned;
+                bucket.warned = true;
+            }
+        }
+
+        if retry_after > Duration::from_secs(0) {
+            return Decision::Deny { retry_after, warn };
+        }
+
+        // Only take tokens when every bucket allows the action.
+        for (key, _) in &keys {
+            buckets.get_mut(key).unwrap().tokens -= 1.0;
+        }
+
+        Decision::Allow
+    }
+
+    fn limit(&self, key: Key) -> Option<Limit> {
+        match key {
+            Key::User(_) => self.limits.user,
+            Key::Channel(_) => self.limits.channel,
+            Key::Guild(_) => self.limits.guild,
+            Key::Load(_) => self.limits.load,
+        }
+    }
+
+    /// Forgets the buckets which are full again, keeping those still
+    /// refilling.
+    fn prune(&self, buckets: &mut HashMap<Key, Bucket>, now: Instant) {
+        buckets.retain(|key, bucket| match self.limit(*key) {
+            Some(limit) => {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.capacity as f64
+            }
+            None => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(Limits {
+            user: Some(Limit::new(2, 10)),
+            channel: Some(Limit::new(3, 10)),
+            guild: None,
+            load: Some(Limit::new(1, 60)),
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Some(Some(Limit::new(5, 10))), Limit::parse("5/10"));
+        assert_eq!(Some(None), Limit::parse("none"));
+        assert_eq!(None, Limit::parse("5"));
+        assert_eq!(None, Limit::parse("0/10"));
+        assert_eq!(None, Limit::parse("a/b"));
+    }
+
+    #[test]
+    fn test_user_limit() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(Decision::Allow, limiter.check_at(1, 10, None, false, now));
+        assert_eq!(Decision::Allow, limiter.check_at(1, 10, None, false, now));
+
+        let retry_after = Duration::from_secs(5);
+        assert_eq!(
+            Decision::Deny {
+                retry_after,
+                warn: true
+            },
+            limiter.check_at(1, 10, None, false, now)
+        );
+        assert_eq!(
+            Decision::Deny {
+                retry_after,
+                warn: false
+            },
+            limiter.check_at(1, 10, None, false, now)
+        );
+
+        // Another user in the same channel.
+        assert_eq!(Decision::Allow, limiter.check_at(2, 10, None, false, now));
+
+        // A token is back after 5 seconds.
+        let later = now + Duration::from_secs(5);
+        assert_eq!(Decision::Allow, limiter.check_at(1, 11, None, false, later));
+    }
+
+    #[test]
+    fn test_channel_limit() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for user in 1..=3 {
+            assert_eq!(
+                Decision::Allow,
+                limiter.check_at(user, 10, None, false, now)
+            );
+        }
+
+        match limiter.check_at(4, 10, None, false, now) {
+            Decision::Deny { warn: true, .. } => {}
+            other => panic!("expected a denial, got {:?}", other),
+        }
+
+        // The denied user did not lose a token.
+        assert_eq!(Decision::Allow, limiter.check_at(4, 11, None, false, now));
+        assert_eq!(Decision::Allow, limiter.check_at(4, 11, None, false, now));
+    }
+
+    #[test]
+    fn test_prune() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(Decision::Allow, limiter.check_at(1, 10, None, false, now));
+        let later = now + Duration::from_secs(4);
+        assert_eq!(Decision::Allow, limiter.check_at(2, 11, None, false, later));
+
+        // After 5 seconds, the first user and channel are full again, but
+        // the second ones are still refilling.
+        let mut buckets = limiter.buckets.lock().unwrap();
+        limiter.prune(&mut buckets, now + Duration::from_secs(5));
+
+        let mut keys: Vec<Key> = buckets.keys().copied().collect();
+        keys.sort_by_key(|key| format!("{:?}", key));
+        assert_eq!(vec![Key::Channel(11), Key::User(2)], keys);
+        assert!(buckets[&Key::User(2)].tokens < 2.0);
+    }
+
+    #[test]
+    fn test_load_limit() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(Decision::Allow, limiter.check_at(1, 10, Some(1), true, now));
+
+        match limiter.check_at(1, 10, Some(1), true, now) {
+            Decision::Deny { retry_after, .. } => assert_eq!(Duration::from_secs(60), retry_after),
+            other => panic!("expected a denial, got {:?}", other),
+        }
+
+        assert_eq!(
+            Decision::Allow,
+            limiter.check_at(1, 10, Some(1), false, now)
+        );
+    }
+}
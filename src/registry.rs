@@ -0,0 +1,205 @@
+use crate::eliza::Eliza;
+use crate::rules::Rules;
+use crate::schema::SCHEMA_FILE;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::Path;
+
+/// A set of named scripts, e.g. `therapist` or `helpdesk`, one of which is
+/// used by default.
+#[derive(Default, Clone)]
+pub struct ScriptRegistry {
+    scripts: BTreeMap<String, Eliza>,
+    default: Option<String>,
+}
+
+impl ScriptRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a single rule file, named after its file stem, or every rule
+    /// file of a directory.
+    ///
+    /// Files which cannot be loaded are skipped; the returned warnings explain
+    /// why, along with the warnings raised while loading the others.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<(Self, Vec<String>)> {
+        let mut registry = ScriptRegistry::new();
+        let warnings = registry.reload(path)?;
+        Ok((registry, warnings))
+    }
+
+    /// Loads the scripts again like `ScriptRegistry::load`. The scripts whose
+    /// files are gone are removed, and those which cannot be loaded anymore
+    /// are kept as they are.
+    pub fn reload<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Vec<String>> {
+        let path = path.as_ref();
+        let mut warnings = Vec::new();
+
+        let files = if path.is_dir() {
+            let mut files = Vec::new();
+
+            for entry in fs::read_dir(path)? {
+                let file = entry?.path();
+                let is_json = file.extension() == Some("json".as_ref());
+                let is_schema = file.file_name() == Some(SCHEMA_FILE.as_ref());
+
+                if is_json && !is_schema {
+                    files.push(file);
+                }
+            }
+
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut previous = mem::take(&mut self.scripts);
+
+        for file in files {
+            let name = match file.file_stem() {
+                Some(stem) => stem.to_string_lossy().to_string(),
+                None => continue,
+            };
+
+            match Rules::load(&file) {
+                Ok((rules, loaded)) => {
+                    for warning in loaded {
+                        warnings.push(format!("{}: {}", name, warning));
+                    }
+
+                    self.insert(name, Eliza::new(rules));
+                }
+                Err(err) => match previous.remove(&name) {
+                    Some(eliza) => {
+                        warnings.push(format!("Keeping the current {}: {}", name, err));
+                        self.insert(name, eliza);
+                    }
+                    None => warnings.push(format!("Skipping {}: {}", name, err)),
+                },
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Seeds every script, see `Eliza::seed`.
+    pub fn seed(&mut self, seed: u64) {
+        for eliza in self.scripts.values_mut() {
+            eliza.seed(seed);
+        }
+    }
+
+    pub fn insert(&mut self, name: String, eliza: Eliza) {
+        self.scripts.insert(name, eliza);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Eliza> {
+        self.scripts.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.scripts.contains_key(name)
+    }
+
+    /// Returns the names of the scripts, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.scripts.keys().map(String::as_str)
+    }
+
+    /// Sets the script used when none is selected. Returns false if there is
+    /// no script with this name.
+    pub fn set_default(&mut self, name: &str) -> bool {
+        if self.contains(name) {
+            self.default = Some(name.to_string());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the name of the default script: the one set with
+    /// `set_default` if it was not removed by `reload`, or else the first one
+    /// in alphabetical order.
+    pub fn default_name(&self) -> Option<&str> {
+        match &self.default {
+            Some(name) if self.contains(name) => Some(name),
+            _ => self.names().next(),
+        }
+    }
+
+    pub fn default_script(&self) -> Option<&Eliza> {
+        self.get(self.default_name()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn eliza(greeting: &str) -> Eliza {
+        let rules = Rules {
+            initial: vec![greeting.to_string()],
+            ..Default::default()
+        };
+
+        Eliza::new(rules)
+    }
+
+    #[test]
+    fn test_default_script() {
+        let mut registry = ScriptRegistry::new();
+        assert!(registry.default_script().is_none());
+
+        registry.insert("tutor".to_string(), eliza("tutor"));
+        registry.insert("helpdesk".to_string(), eliza("helpdesk"));
+        assert_eq!(Some("helpdesk"), registry.default_name());
+
+        assert!(!registry.set_default("therapist"));
+        assert!(registry.set_default("tutor"));
+        assert_eq!("tutor", registry.default_script().unwrap().greeting());
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let dir = TempDir::new("registry");
+
+        fs::copy("rules/therapist.json", dir.join("therapist.json")).unwrap();
+        fs::copy("rules/schema.json", dir.join(SCHEMA_FILE)).unwrap();
+        fs::write(dir.join("broken.json"), "{}").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let (registry, warnings) = ScriptRegistry::load(&dir).unwrap();
+
+        assert_eq!(vec!["therapist"], registry.names().collect::<Vec<_>>());
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].starts_with("Skipping broken"));
+    }
+
+    #[test]
+    fn test_reload_directory() {
+        let dir = TempDir::new("registry-reload");
+
+        for name in &["therapist", "tutor", "helpdesk"] {
+            fs::copy("rules/therapist.json", dir.join(format!("{}.json", name))).unwrap();
+        }
+
+        let (mut registry, _) = ScriptRegistry::load(&dir).unwrap();
+        assert!(registry.set_default("tutor"));
+
+        fs::remove_file(dir.join("tutor.json")).unwrap();
+        fs::write(dir.join("helpdesk.json"), "{").unwrap();
+
+        let warnings = registry.reload(&dir).unwrap();
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].starts_with("Keeping the current helpdesk"));
+        assert_eq!(
+            vec!["helpdesk", "therapist"],
+            registry.names().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("helpdesk"), registry.default_name());
+    }
+}
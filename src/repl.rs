@@ -0,0 +1,426 @@
+use crate::cli::ScriptOptions;
+use eliza::chat::{Event, Reply, Sessions, Transport};
+use eliza::registry::ScriptRegistry;
+use eliza::Eliza;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the only conversation in the terminal, see `chat::Sessions`.
+const CONVERSATION: &str = "terminal";
+
+const HELP: &str = "\
+:trace            toggle the explanation of responses
+:reload           read the rules again
+:reset            start a new conversation
+:keywords         list the keywords of the current script
+:save <file>      write the conversation to a file
+:seed [n]         show or set the seed of responses
+:use [name]       list the scripts, or switch to another one
+:help             show this message";
+
+/// A command of the REPL, starting with a colon.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Meta {
+    Trace,
+    Reload,
+    Reset,
+    Keywords,
+    Save(PathBuf),
+    Seed(Option<u64>),
+    Use(Option<String>),
+    Help,
+}
+
+impl Meta {
+    /// Reads a command. Returns `None` if the line is a message to Eliza,
+    /// and an error if it is not a valid command.
+    pub fn parse(line: &str) -> Option<Result<Meta, String>> {
+        let line = line.trim();
+
+        if !line.starts_with(':') {
+            return None;
+        }
+
+        let mut words = line[1..].splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or("");
+        let arg = words.next().map(str::trim).filter(|a| !a.is_empty());
+
+        let meta = match (name, arg) {
+            ("trace", None) => Ok(Meta::Trace),
+            ("reload", None) => Ok(Meta::Reload),
+            ("reset", None) => Ok(Meta::Reset),
+            ("keywords", None) => Ok(Meta::Keywords),
+            ("save", Some(path)) => Ok(Meta::Save(PathBuf::from(path))),
+            ("save", None) => Err("Usage: :save <file>".to_string()),
+            ("seed", None) => Ok(Meta::Seed(None)),
+            ("seed", Some(seed)) => seed
+                .parse()
+                .map(|seed| Meta::Seed(Some(seed)))
+                .map_err(|_| format!("'{}' is not a valid seed.", seed)),
+            ("use", name) => Ok(Meta::Use(name.map(str::to_string))),
+            ("help", None) => Ok(Meta::Help),
+            _ => Err(format!("Unknown command '{}', see :help.", line)),
+        };
+
+        Some(meta)
+    }
+}
+
+/// The state of a conversation in the terminal.
+pub struct Repl<'a> {
+    path: &'a Path,
+    scripts: ScriptRegistry,
+    current: String,
+    seed: Option<u64>,
+    trace: bool,
+    sessions: Sessions,
+    /// The greeting, then the messages prefixed with `> ` and the responses.
+    transcript: Vec<String>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(
+        path: &'a Path,
+        scripts: ScriptRegistry,
+        current: String,
+        options: &ScriptOptions,
+    ) -> Self {
+        Repl {
+            path,
+            scripts,
+            current,
+            seed: options.seed,
+            trace: options.trace,
+            sessions: Sessions::new(),
+            transcript: Vec::new(),
+        }
+    }
+
+    fn eliza(&self) -> &Eliza {
+        self.scripts.get(&self.current).unwrap()
+    }
+
+    /// Chats until the user or Eliza ends the conversation. The lines are
+    /// kept in the file of `ELIZA_HISTORY`, `~/.eliza_history` by default.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut editor = Editor::<()>::new();
+        let history = history_path();
+
+        if let Some(history) = &history {
+            // There is no history the first time.
+            editor.load_history(history).ok();
+        }
+
+        self.greet();
+
+        loop {
+            let line = match editor.readline("> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(io::Error::other(err)),
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            editor.add_history_entry(line.as_str());
+
+            let done = match Meta::parse(&line) {
+                Some(Ok(meta)) => {
+                    self.meta(meta);
+                    false
+                }
+                Some(Err(err)) => {
+                    eprintln!("{}", err);
+                    false
+                }
+                None => self.respond(line.trim()),
+            };
+
+            if done {
+                break;
+            }
+        }
+
+        if let Some(history) = &history {
+            if let Err(err) = editor.save_history(history) {
+                eprintln!("Warning: could not save the history: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn greet(&mut self) {
+        let event = Event::Open(CONVERSATION.to_string());
+
+        if let Some(reply) = self.sessions.handle(self.eliza(), event) {
+            println!("{}", reply.text);
+            self.transcript.push(reply.text);
+        }
+    }
+
+    /// Prints the response to a message. Returns whether the conversation is
+    /// over.
+    fn respond(&mut self, input: &str) -> bool {
+        let event = Event::Message {
+            conversation: CONVERSATION.to_string(),
+            text: input.to_string(),
+        };
+
+        let reply = match self.sessions.handle(self.eliza(), event) {
+            Some(reply) => reply,
+            None => return false,
+        };
+
+        print_reply(&reply, self.trace);
+        self.transcript.push(format!("> {}", input));
+        self.transcript.push(reply.text);
+        reply.is_farewell
+    }
+
+    /// Switches to reloaded scripts, and to the default one if the current
+    /// one was removed.
+    fn reloaded(&mut self, mut scripts: ScriptRegistry) {
+        let default = match scripts.default_name() {
+            Some(name) => name.to_string(),
+            None => {
+                eprintln!(
+                    "Keeping the current rules: no rules left in '{}'.",
+                    self.path.display()
+                );
+                return;
+            }
+        };
+
+        if let Some(seed) = self.seed {
+            scripts.seed(seed);
+        }
+
+        self.scripts = scripts;
+        println!("Reloaded rules from '{}'.", self.path.display());
+
+        if !self.scripts.contains(&self.current) {
+            println!("'{}' was removed, using '{}'.", self.current, default);
+            self.current = default;
+            self.greet();
+        }
+    }
+
+    fn meta(&mut self, meta: Meta) {
+        match meta {
+            Meta::Trace => {
+                self.trace = !self.trace;
+                println!("Tracing is {}.", if self.trace { "on" } else { "off" });
+            }
+            Meta::Reload => {
+                let mut scripts = self.scripts.clone();
+
+                match scripts.reload(self.path) {
+                    Ok(warnings) => {
+                        crate::print_warnings(&warnings);
+                        self.reloaded(scripts);
+                    }
+                    Err(err) => eprintln!("Keeping the current rules: {}", err),
+                }
+            }
+            Meta::Reset => {
+                // Seeding again replays the same responses.
+                if let Some(seed) = self.seed {
+                    self.scripts.seed(seed);
+                }
+
+                self.transcript.clear();
+                self.greet();
+            }
+            Meta::Keywords => {
+                for keyword in &self.eliza().rules().keywords {
+                    println!("{} ({})", keyword.word, keyword.weight);
+                }
+            }
+            Meta::Save(path) => match fs::write(&path, self.transcript.join("\n") + "\n") {
+                Ok(()) => println!("Saved the conversation to '{}'.", path.display()),
+                Err(err) => eprintln!("Could not write '{}': {}", path.display(), err),
+            },
+            Meta::Seed(None) => match self.seed {
+                Some(seed) => println!("The seed is {}.", seed),
+                None => println!("There is no seed, responses are random."),
+            },
+            Meta::Seed(Some(seed)) => {
+                self.seed = Some(seed);
+                self.scripts.seed(seed);
+                println!("The seed is now {}.", seed);
+            }
+            Meta::Use(None) => {
+                for name in self.scripts.names() {
+                    let marker = if name == self.current { "*" } else { " " };
+                    println!("{} {}", marker, name);
+                }
+            }
+            Meta::Use(Some(name)) => {
+                if self.scripts.contains(&name) {
+                    self.current = name;
+                    self.greet();
+                } else {
+                    eprintln!("Unknown script '{}'.", name);
+                }
+            }
+            Meta::Help => println!("{}", HELP),
+        }
+    }
+}
+
+/// Replays messages, one per line, printing them with their replies like
+/// `:save` does. Stops at the first farewell.
+pub struct ReplayTransport {
+    lines: std::vec::IntoIter<String>,
+    opened: bool,
+    done: bool,
+    trace: bool,
+}
+
+impl ReplayTransport {
+    pub fn new(lines: Vec<String>, trace: bool) -> Self {
+        ReplayTransport {
+            lines: lines.into_iter(),
+            opened: false,
+            done: false,
+            trace,
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn receive(&mut self) -> io::Result<Option<Event>> {
+        if !self.opened {
+            self.opened = true;
+            return Ok(Some(Event::Open(CONVERSATION.to_string())));
+        }
+
+        if self.done {
+            return Ok(None);
+        }
+
+        let line = self.lines.find(|line| !line.trim().is_empty());
+
+        Ok(line.map(|line| {
+            println!("> {}", line.trim());
+
+            Event::Message {
+                conversation: CONVERSATION.to_string(),
+                text: line,
+            }
+        }))
+    }
+
+    fn send(&mut self, reply: &Reply) -> io::Result<()> {
+        print_reply(reply, self.trace);
+        self.done = reply.is_farewell;
+        Ok(())
+    }
+}
+
+/// Prints a reply, and its trace to the standard error if asked for.
+fn print_reply(reply: &Reply, trace: bool) {
+    println!("{}", reply.text);
+
+    if let (true, Some(trace)) = (trace, &reply.trace) {
+        for line in trace.to_string().lines() {
+            eprintln!("  | {}", line);
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("ELIZA_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::var_os("HOME").map(|home| Path::new(&home).join(".eliza_history")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use eliza::Rules;
+
+    fn repl(path: &Path) -> Repl<'_> {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut scripts = ScriptRegistry::new();
+        scripts.insert("therapist".to_string(), Eliza::new(rules));
+        scripts.seed(1);
+
+        let options = ScriptOptions {
+            seed: Some(1),
+            trace: false,
+            script: None,
+        };
+
+        Repl::new(path, scripts, "therapist".to_string(), &options)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(None, Meta::parse("hello"));
+        assert_eq!(Some(Ok(Meta::Trace)), Meta::parse(":trace"));
+        assert_eq!(Some(Ok(Meta::Reset)), Meta::parse("  :reset "));
+        assert_eq!(
+            Some(Ok(Meta::Save(PathBuf::from("out.txt")))),
+            Meta::parse(":save out.txt")
+        );
+        assert_eq!(Some(Ok(Meta::Seed(Some(42)))), Meta::parse(":seed 42"));
+        assert_eq!(Some(Ok(Meta::Seed(None))), Meta::parse(":seed"));
+        assert_eq!(
+            Some(Ok(Meta::Use(Some("doctor".to_string())))),
+            Meta::parse(":use doctor")
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(Meta::parse(":save"), Some(Err(_))));
+        assert!(matches!(Meta::parse(":seed abc"), Some(Err(_))));
+        assert!(matches!(Meta::parse(":trace on"), Some(Err(_))));
+        assert!(matches!(Meta::parse(":dance"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_reset_and_save() {
+        let path = Path::new("rules/therapist.json");
+        let mut repl = repl(path);
+
+        repl.greet();
+        repl.respond("I am sad");
+        let first = repl.transcript.clone();
+        assert_eq!(3, first.len());
+        assert_eq!("> I am sad", first[1]);
+
+        // The same seed gives the same conversation again.
+        repl.meta(Meta::Reset);
+        repl.respond("I am sad");
+        assert_eq!(first, repl.transcript);
+
+        let dir = TempDir::new("repl");
+        let file = dir.join("transcript.txt");
+        repl.meta(Meta::Save(file.clone()));
+        assert_eq!(first.join("\n") + "\n", fs::read_to_string(&file).unwrap());
+    }
+
+    #[test]
+    fn test_reload_removed_script() {
+        let dir = TempDir::new("repl-reload");
+        fs::copy("rules/therapist.json", dir.join("tutor.json")).unwrap();
+
+        let mut repl = repl(&dir);
+        repl.meta(Meta::Reload);
+
+        assert_eq!("tutor", repl.current);
+        assert!(!repl.scripts.contains("therapist"));
+    }
+}
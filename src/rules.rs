@@ -1,27 +1,370 @@
+use crate::schema::{self, CURRENT_VERSION};
+use crate::validation::ValidationError;
 use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Rules {
+    /// Path or URL of a JSON Schema, only used by editors.
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Version of the rules format, see `schema::migrate`.
+    pub version: u64,
+    /// Rule files this one builds on, relative to this file. They are merged
+    /// in order, and this file is then merged on top of them.
+    #[serde(default, alias = "include", skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    #[serde(default)]
     pub initial: Vec<String>,
-    #[serde(rename = "final")]
+    #[serde(default, rename = "final")]
     pub final_: Vec<String>,
+    #[serde(default)]
     pub quit: Vec<String>,
+    #[serde(default)]
     pub pre: Vec<Replacement>,
+    #[serde(default)]
     pub post: Vec<Replacement>,
-    #[serde(rename = "synon")]
+    #[serde(default, rename = "synon")]
     pub synonyms: Vec<Synonym>,
-    #[serde(rename = "key")]
+    #[serde(default, rename = "key")]
     pub keywords: Vec<Keyword>,
 }
 
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            schema: None,
+            version: CURRENT_VERSION,
+            extends: vec![],
+            initial: vec![],
+            final_: vec![],
+            quit: vec![],
+            pre: vec![],
+            post: vec![],
+            synonyms: vec![],
+            keywords: vec![],
+        }
+    }
+}
+
+/// Parses rules in JSON, upgrading them from older versions of the format.
+/// See `Rules::from_json` for the fields dropped by the upgrade.
+impl FromStr for Rules {
+    type Err = ParseError;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        Rules::from_json(contents).map(|(rules, _)| rules)
+    }
+}
+
 impl Rules {
-    pub fn sort_keywords_by_reverse_weight(&mut self) {
-        self.keywords.sort_by_key(|k| Reverse(k.weight));
+    /// Parses rules in JSON like `str::parse`, and also returns the fields
+    /// dropped while upgrading them, see `schema::migrate`.
+    pub fn from_json(contents: &str) -> Result<(Rules, Vec<String>), ParseError> {
+        let value = serde_json::from_str(contents).map_err(ParseError::Json)?;
+        let (value, dropped) = schema::migrate(value)?;
+        let rules = serde_json::from_value(value).map_err(ParseError::Json)?;
+        Ok((rules, dropped))
+    }
+
+    /// Reads a rule file, resolving its `extends` directives.
+    ///
+    /// The conflicts found while merging the included files, and the fields
+    /// dropped while upgrading them, are returned along with the rules so that
+    /// callers can report them.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<(Rules, Vec<LoadWarning>), RulesError> {
+        let mut warnings = Vec::new();
+        let rules = Rules::resolve(path.as_ref(), &mut vec![], &mut warnings)?;
+        Ok((rules, warnings))
+    }
+
+    /// Reads a rule file like `Rules::from_file`, and checks that Eliza can
+    /// use it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(Rules, Vec<LoadWarning>), RulesError> {
+        let path = path.as_ref();
+        let (rules, warnings) = Rules::from_file(path)?;
+
+        match rules.validate() {
+            Ok(()) => Ok((rules, warnings)),
+            Err(errors) => Err(RulesError::Invalid(path.to_path_buf(), errors)),
+        }
+    }
+
+    fn resolve(
+        path: &Path,
+        stack: &mut Vec<PathBuf>,
+        warnings: &mut Vec<LoadWarning>,
+    ) -> Result<Rules, RulesError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| RulesError::Io(path.to_path_buf(), e))?;
+
+        if stack.contains(&canonical) {
+            return Err(RulesError::Cycle(path.to_path_buf()));
+        }
+
+        let contents =
+            fs::read_to_string(path).map_err(|e| RulesError::Io(path.to_path_buf(), e))?;
+        let (mut rules, dropped) =
+            Rules::from_json(&contents).map_err(|e| RulesError::Parse(path.to_path_buf(), e))?;
+
+        warnings.extend(dropped.into_iter().map(|field| LoadWarning::DroppedField {
+            path: path.to_path_buf(),
+            field,
+        }));
+
+        if rules.extends.is_empty() {
+            return Ok(rules);
+        }
+
+        stack.push(canonical);
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut base = Rules::default();
+
+        for include in rules.extends.drain(..) {
+            let included = Rules::resolve(&directory.join(include), stack, warnings)?;
+            let conflicts = base.merge(included);
+            warnings.extend(conflicts.into_iter().map(LoadWarning::Conflict));
+        }
+
+        stack.pop();
+
+        let conflicts = base.merge(rules);
+        warnings.extend(conflicts.into_iter().map(LoadWarning::Conflict));
+        Ok(base)
+    }
+
+    /// Merges an overlay on top of these rules.
+    ///
+    /// - `initial` and `final` are replaced by the overlay's, unless it has none.
+    /// - `quit` words are added to the existing ones.
+    /// - `pre` and `post` replacements override the ones with the same input.
+    /// - synonym lists with the same label are united.
+    /// - decompositions of keywords that already exist are appended, or
+    ///   replace the existing ones if the overlay keyword has `"merge": "replace"`.
+    ///   The overlay's weight is kept, and its examples are added.
+    pub fn merge(&mut self, overlay: Rules) -> Vec<MergeConflict> {
+        let mut conflicts = Vec::new();
+
+        if !overlay.initial.is_empty() {
+            self.initial = overlay.initial;
+        }
+
+        if !overlay.final_.is_empty() {
+            self.final_ = overlay.final_;
+        }
+
+        for word in overlay.quit {
+            if !self.quit.contains(&word) {
+                self.quit.push(word);
+            }
+        }
+
+        merge_replacements("pre", &mut self.pre, overlay.pre, &mut conflicts);
+        merge_replacements("post", &mut self.post, overlay.post, &mut conflicts);
+
+        for synonym in overlay.synonyms {
+            match self.synonyms.iter_mut().find(|s| s.label == synonym.label) {
+                Some(existing) => {
+                    for word in synonym.list {
+                        if !existing.list.contains(&word) {
+                            existing.list.push(word);
+                        }
+                    }
+                }
+                None => self.synonyms.push(synonym),
+            }
+        }
+
+        for keyword in overlay.keywords {
+            let existing = match self.keywords.iter_mut().find(|k| k.word == keyword.word) {
+                Some(existing) => existing,
+                None => {
+                    self.keywords.push(keyword);
+                    continue;
+                }
+            };
+
+            if existing.weight != keyword.weight {
+                conflicts.push(MergeConflict::KeywordWeight {
+                    word: keyword.word.clone(),
+                    base: existing.weight,
+                    overlay: keyword.weight,
+                });
+                existing.weight = keyword.weight;
+            }
+
+            match keyword.merge {
+                MergeMode::Append => existing.decomposition.extend(keyword.decomposition),
+                MergeMode::Replace => existing.decomposition = keyword.decomposition,
+            }
+
+            existing.examples.extend(keyword.examples);
+        }
+
+        conflicts
+    }
+
+    /// Sorts the keywords by decreasing weight, keeping the order of those
+    /// with the same weight. Returns the former index of each keyword.
+    pub fn sort_keywords_by_reverse_weight(&mut self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.keywords.len()).collect();
+        order.sort_by_key(|&i| Reverse(self.keywords[i].weight));
+
+        let mut keywords: Vec<Option<Keyword>> = self.keywords.drain(..).map(Some).collect();
+        self.keywords = order.iter().map(|&i| keywords[i].take().unwrap()).collect();
+        order
+    }
+}
+
+fn merge_replacements(
+    list: &'static str,
+    base: &mut Vec<Replacement>,
+    overlay: Vec<Replacement>,
+    conflicts: &mut Vec<MergeConflict>,
+) {
+    for replacement in overlay {
+        match base.iter_mut().find(|r| r.from == replacement.from) {
+            Some(existing) if existing.to != replacement.to => {
+                conflicts.push(MergeConflict::Replacement {
+                    list,
+                    from: replacement.from,
+                    base: existing.to.clone(),
+                    overlay: replacement.to.clone(),
+                });
+                existing.to = replacement.to;
+            }
+            Some(_) => {}
+            None => base.push(replacement),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Json(e) => write!(f, "{}", e),
+            ParseError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported version {} (the latest supported version is {})",
+                version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum RulesError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ParseError),
+    Cycle(PathBuf),
+    Invalid(PathBuf, Vec<ValidationError>),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RulesError::Io(path, e) => write!(f, "could not read '{}': {}", path.display(), e),
+            RulesError::Parse(path, e) => write!(f, "could not parse '{}': {}", path.display(), e),
+            RulesError::Cycle(path) => write!(f, "'{}' includes itself", path.display()),
+            RulesError::Invalid(path, errors) => {
+                write!(f, "'{}' is invalid:", path.display())?;
+
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+
+                Ok(())
+            }
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl std::error::Error for RulesError {}
+
+/// Something worth reporting about a rule file which did not stop it from
+/// loading.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadWarning {
+    Conflict(MergeConflict),
+    /// A field of an older version of the format, unknown to the current one.
+    DroppedField {
+        path: PathBuf,
+        field: String,
+    },
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadWarning::Conflict(conflict) => write!(f, "{}", conflict),
+            LoadWarning::DroppedField { path, field } => {
+                write!(f, "'{}': dropped unknown field '{}'", path.display(), field)
+            }
+        }
+    }
+}
+
+/// A difference between two rule sets that could not be merged silently. The
+/// overlay's value always wins.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MergeConflict {
+    KeywordWeight {
+        word: String,
+        base: u8,
+        overlay: u8,
+    },
+    Replacement {
+        list: &'static str,
+        from: String,
+        base: String,
+        overlay: String,
+    },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeConflict::KeywordWeight {
+                word,
+                base,
+                overlay,
+            } => write!(
+                f,
+                "keyword '{}' has weight {} but is overridden with weight {}",
+                word, base, overlay
+            ),
+            MergeConflict::Replacement {
+                list,
+                from,
+                base,
+                overlay,
+            } => write!(
+                f,
+                "{} replacement of '{}' is '{}' but is overridden with '{}'",
+                list, from, base, overlay
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Replacement {
     #[serde(rename = "in")]
     pub from: String,
@@ -29,29 +372,248 @@ pub struct Replacement {
     pub to: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Synonym {
     pub label: String,
     pub list: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Keyword {
     pub word: String,
     pub weight: u8,
     #[serde(rename = "decomp")]
     pub decomposition: Vec<Decomposition>,
+    /// How the decompositions are merged into an existing keyword with the
+    /// same word, see `Rules::merge`.
+    #[serde(default, skip_serializing_if = "MergeMode::is_append")]
+    pub merge: MergeMode,
+    /// Inputs which should be answered by this keyword, see
+    /// `Rules::check_examples`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    #[default]
+    Append,
+    Replace,
+}
+
+impl MergeMode {
+    fn is_append(&self) -> bool {
+        *self == MergeMode::Append
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Decomposition {
     pub pattern: String,
     pub reasmb: Vec<String>,
+    /// Inputs which should be answered by this decomposition, see
+    /// `Rules::check_examples`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+}
+
+/// An input documenting a keyword or a decomposition, and optionally the
+/// groups its pattern should capture.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Example {
+    Input(String),
+    Groups { input: String, groups: Vec<String> },
+}
+
+impl Example {
+    pub fn input(&self) -> &str {
+        match self {
+            Example::Input(input) | Example::Groups { input, .. } => input,
+        }
+    }
+
+    pub fn groups(&self) -> Option<&[String]> {
+        match self {
+            Example::Input(_) => None,
+            Example::Groups { groups, .. } => Some(groups),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TempDir;
+
+    fn parse(json: &str) -> Rules {
+        json.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_fields() {
+        assert!(
+            r#"{"version": 2, "key": [{"word": "a", "weight": 0, "decomp": [], "wieght": 1}]}"#
+                .parse::<Rules>()
+                .is_err()
+        );
+        assert!(
+            r#"{"key": [{"word": "a", "weight": 0, "decomp": [], "wieght": 1}]}"#
+                .parse::<Rules>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_merge_overrides_initial_and_final() {
+        let mut rules = parse(r#"{"initial": ["hi"], "final": ["bye"]}"#);
+        rules.merge(parse(r#"{"initial": ["hello"]}"#));
+
+        assert_eq!(vec!["hello"], rules.initial);
+        assert_eq!(vec!["bye"], rules.final_);
+    }
+
+    #[test]
+    fn test_merge_unites_quit_and_synonyms() {
+        let mut rules = parse(
+            r#"{"quit": ["bye"], "synon": [{"label": "@happy", "list": ["happy", "glad"]}]}"#,
+        );
+        rules.merge(parse(
+            r#"{
+                "quit": ["bye", "quit"],
+                "synon": [
+                    {"label": "@happy", "list": ["glad", "elated"]},
+                    {"label": "@sad", "list": ["sad"]}
+                ]
+            }"#,
+        ));
+
+        assert_eq!(vec!["bye", "quit"], rules.quit);
+        assert_eq!(2, rules.synonyms.len());
+        assert_eq!(vec!["happy", "glad", "elated"], rules.synonyms[0].list);
+        assert_eq!("@sad", rules.synonyms[1].label);
+    }
+
+    #[test]
+    fn test_merge_keywords() {
+        let mut rules = parse(
+            r#"{"key": [
+                {"word": "hello", "weight": 1, "decomp": [{"pattern": "*", "reasmb": ["a"]}]},
+                {"word": "world", "weight": 1, "decomp": [{"pattern": "*", "reasmb": ["b"]}]}
+            ]}"#,
+        );
+        let conflicts = rules.merge(parse(
+            r#"{"key": [
+                {"word": "hello", "weight": 1, "decomp": [{"pattern": "* hello", "reasmb": ["c"]}]},
+                {"word": "world", "weight": 2, "merge": "replace", "decomp": [{"pattern": "* world", "reasmb": ["d"]}]},
+                {"word": "new", "weight": 0, "decomp": []}
+            ]}"#,
+        ));
+
+        let patterns = |word: &str| -> Vec<String> {
+            let keyword = rules.keywords.iter().find(|k| k.word == word).unwrap();
+            keyword
+                .decomposition
+                .iter()
+                .map(|d| d.pattern.clone())
+                .collect()
+        };
+
+        assert_eq!(vec!["*", "* hello"], patterns("hello"));
+        assert_eq!(vec!["* world"], patterns("world"));
+        assert_eq!(3, rules.keywords.len());
+        assert_eq!(2, rules.keywords[1].weight);
+        assert_eq!(
+            vec![MergeConflict::KeywordWeight {
+                word: "world".to_string(),
+                base: 1,
+                overlay: 2,
+            }],
+            conflicts
+        );
+    }
+
+    #[test]
+    fn test_merge_replacements() {
+        let mut rules = parse(r#"{"pre": [{"in": "dont", "out": "don't"}]}"#);
+        let conflicts = rules.merge(parse(
+            r#"{"pre": [{"in": "dont", "out": "do not"}, {"in": "cant", "out": "can't"}]}"#,
+        ));
+
+        assert_eq!(2, rules.pre.len());
+        assert_eq!("do not", rules.pre[0].to);
+        assert_eq!(
+            vec![MergeConflict::Replacement {
+                list: "pre",
+                from: "dont".to_string(),
+                base: "don't".to_string(),
+                overlay: "do not".to_string(),
+            }],
+            conflicts
+        );
+    }
+
+    #[test]
+    fn test_from_file_extends() {
+        let dir = TempDir::new("extends");
+        fs::create_dir(dir.join("base")).unwrap();
+        fs::write(
+            dir.join("base").join("base.json"),
+            r#"{"initial": ["hi"], "final": ["bye"], "quit": ["bye"], "key": [{"word": "xnone", "weight": 0, "decomp": []}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("team.json"),
+            r#"{"extends": ["base/base.json"], "initial": ["hello team"], "key": [{"word": "xnone", "weight": 3, "decomp": []}]}"#,
+        )
+        .unwrap();
+
+        let (rules, warnings) = Rules::from_file(dir.join("team.json")).unwrap();
+
+        assert!(rules.extends.is_empty());
+        assert_eq!(vec!["hello team"], rules.initial);
+        assert_eq!(vec!["bye"], rules.final_);
+        assert_eq!(1, rules.keywords.len());
+        assert_eq!(1, warnings.len());
+    }
+
+    #[test]
+    fn test_from_file_dropped_fields() {
+        let dir = TempDir::new("dropped");
+        let path = dir.join("old.json");
+        fs::write(
+            &path,
+            r#"{"$schema": "schema.json", "comment": "old", "initial": ["hi"]}"#,
+        )
+        .unwrap();
+
+        let (rules, warnings) = Rules::from_file(&path).unwrap();
+
+        assert_eq!(Some("schema.json".to_string()), rules.schema);
+        assert_eq!(
+            vec![LoadWarning::DroppedField {
+                path,
+                field: "comment".to_string(),
+            }],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_from_file_cycle() {
+        let dir = TempDir::new("cycle");
+        fs::write(dir.join("a.json"), r#"{"include": ["b.json"]}"#).unwrap();
+        fs::write(dir.join("b.json"), r#"{"include": ["a.json"]}"#).unwrap();
+
+        match Rules::from_file(dir.join("a.json")) {
+            Err(RulesError::Cycle(path)) => assert_eq!(dir.join("a.json"), path),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
 
     #[test]
     pub fn test_keywords_by_reverse_weight() {
@@ -60,16 +622,19 @@ mod tests {
                 word: "".to_string(),
                 weight: 0,
                 decomposition: vec![],
+                ..Default::default()
             },
             Keyword {
                 word: "".to_string(),
                 weight: 2,
                 decomposition: vec![],
+                ..Default::default()
             },
             Keyword {
                 word: "".to_string(),
                 weight: 1,
                 decomposition: vec![],
+                ..Default::default()
             },
         ];
 
@@ -81,13 +646,15 @@ mod tests {
             post: vec![],
             synonyms: vec![],
             keywords,
+            ..Default::default()
         };
 
-        rules.sort_keywords_by_reverse_weight();
+        let order = rules.sort_keywords_by_reverse_weight();
 
         let result: Vec<u8> = rules.keywords.iter().map(|k| k.weight).collect();
         let expected = vec![2, 1, 0];
 
         assert_eq!(expected, result);
+        assert_eq!(vec![1, 2, 0], order);
     }
 }
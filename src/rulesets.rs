@@ -0,0 +1,229 @@
+use crate::store::{RuleStore, StoreError};
+use eliza::Eliza;
+use eliza::Rules;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where a rule set applies. Channel ids are unique across guilds, and direct
+/// message channels do not belong to any guild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Guild(u64),
+    Channel(u64),
+}
+
+impl Scope {
+    /// Returns the scopes a message is answered from, the most specific one
+    /// first.
+    pub fn chain(guild: Option<u64>, channel: u64) -> Vec<Scope> {
+        let mut chain = vec![Scope::Channel(channel)];
+
+        if let Some(guild) = guild {
+            chain.push(Scope::Guild(guild));
+        }
+
+        chain.push(Scope::Global);
+        chain
+    }
+
+    /// Name of this scope in storage, e.g. `guild-<id>`.
+    pub fn name(&self) -> String {
+        match self {
+            Scope::Global => "global".to_string(),
+            Scope::Guild(id) => format!("guild-{}", id),
+            Scope::Channel(id) => format!("channel-{}", id),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scope> {
+        if name == "global" {
+            Some(Scope::Global)
+        } else if let Some(id) = name.strip_prefix("guild-") {
+            id.parse().ok().map(Scope::Guild)
+        } else if let Some(id) = name.strip_prefix("channel-") {
+            id.parse().ok().map(Scope::Channel)
+        } else {
+            None
+        }
+    }
+
+    /// Name of the file storing the rules of this scope.
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.name())
+    }
+
+    pub fn from_file_name(name: &str) -> Option<Scope> {
+        Scope::from_name(name.strip_suffix(".json")?)
+    }
+}
+
+/// The rule sets loaded for each scope. Cloning them is cheap, so that they
+/// can be replaced as a whole while being read.
+#[derive(Default, Clone)]
+pub struct Rulesets {
+    elizas: HashMap<Scope, Arc<Eliza>>,
+}
+
+impl Rulesets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the rule sets stored in a directory again, see
+    /// `Scope::file_name`. A missing directory has no rule sets.
+    ///
+    /// The scopes whose files are gone are dropped. Those whose files cannot
+    /// be loaded keep their current rules, or are skipped if they have none;
+    /// the returned warnings explain why.
+    pub fn reload_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<(Self, Vec<String>)> {
+        let mut rulesets = Rulesets::new();
+        let mut warnings = Vec::new();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((rulesets, warnings)),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            let scope = match path
+                .file_name()
+                .and_then(|n| Scope::from_file_name(&n.to_string_lossy()))
+            {
+                Some(scope) => scope,
+                None => continue,
+            };
+
+            match Rules::load(&path) {
+                Ok((rules, loaded)) => {
+                    for warning in loaded {
+                        warnings.push(format!("{:?}: {}", scope, warning));
+                    }
+
+                    rulesets.insert(scope, Eliza::new(rules));
+                }
+                Err(err) => match self.elizas.get(&scope) {
+                    Some(current) => {
+                        rulesets.elizas.insert(scope, Arc::clone(current));
+                        warnings.push(format!("Keeping the current {:?}: {}", scope, err));
+                    }
+                    None => warnings.push(format!("Skipping {:?}: {}", scope, err)),
+                },
+            }
+        }
+
+        Ok((rulesets, warnings))
+    }
+
+    /// Loads the rule sets of every scope of a store. Rules which cannot be
+    /// loaded, or are invalid, are skipped; the returned warnings explain why.
+    pub fn load_store(store: &dyn RuleStore) -> Result<(Self, Vec<String>), StoreError> {
+        let mut rulesets = Rulesets::new();
+        let mut warnings = Vec::new();
+
+        for scope in store.list()? {
+            match store.load(scope) {
+                Ok(Some(rules)) => match rules.validate() {
+                    Ok(_) => rulesets.insert(scope, Eliza::new(rules)),
+                    Err(errors) => {
+                        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                        warnings.push(format!("Skipping {:?}: {}", scope, errors.join("; ")));
+                    }
+                },
+                Ok(None) => {}
+                Err(err) => warnings.push(format!("Skipping {:?}: {}", scope, err)),
+            }
+        }
+
+        Ok((rulesets, warnings))
+    }
+
+    pub fn insert(&mut self, scope: Scope, eliza: Eliza) {
+        self.elizas.insert(scope, Arc::new(eliza));
+    }
+
+    /// Returns the rules answering in a channel: the ones of the channel, or
+    /// else of its guild, or else the global ones.
+    pub fn get(&self, guild: Option<u64>, channel: u64) -> Option<&Eliza> {
+        Scope::chain(guild, channel)
+            .iter()
+            .find_map(|scope| self.elizas.get(scope))
+            .map(Arc::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn eliza(greeting: &str) -> Eliza {
+        let rules = Rules {
+            initial: vec![greeting.to_string()],
+            ..Default::default()
+        };
+
+        Eliza::new(rules)
+    }
+
+    #[test]
+    fn test_file_name() {
+        for scope in &[Scope::Global, Scope::Guild(1), Scope::Channel(2)] {
+            assert_eq!(Some(*scope), Scope::from_file_name(&scope.file_name()));
+        }
+
+        assert_eq!(None, Scope::from_file_name("guild-abc.json"));
+        assert_eq!(None, Scope::from_file_name("global.txt"));
+    }
+
+    #[test]
+    fn test_get_falls_back_to_broader_scopes() {
+        let mut rulesets = Rulesets::new();
+        assert!(rulesets.get(Some(1), 10).is_none());
+
+        rulesets.insert(Scope::Global, eliza("global"));
+        rulesets.insert(Scope::Guild(1), eliza("guild"));
+        rulesets.insert(Scope::Channel(10), eliza("channel"));
+
+        assert_eq!("channel", rulesets.get(Some(1), 10).unwrap().greeting());
+        assert_eq!("guild", rulesets.get(Some(1), 11).unwrap().greeting());
+        assert_eq!("global", rulesets.get(Some(2), 12).unwrap().greeting());
+        assert_eq!("global", rulesets.get(None, 13).unwrap().greeting());
+    }
+
+    #[test]
+    fn test_reload_dir() {
+        let dir = TempDir::new("rulesets-reload");
+
+        let (mut rules, _) = Rules::from_file("rules/therapist.json").unwrap();
+        let write = |scope: Scope, rules: &Rules| {
+            let content = serde_json::to_string(rules).unwrap();
+            fs::write(dir.join(scope.file_name()), content).unwrap();
+        };
+
+        rules.initial = vec!["guild".to_string()];
+        write(Scope::Guild(1), &rules);
+        write(Scope::Guild(2), &rules);
+        rules.initial = vec!["channel".to_string()];
+        write(Scope::Channel(10), &rules);
+
+        let (rulesets, warnings) = Rulesets::new().reload_dir(&dir).unwrap();
+        assert!(warnings.is_empty());
+
+        // A file removed by hand, another one broken, and one edited.
+        fs::remove_file(dir.join(Scope::Channel(10).file_name())).unwrap();
+        fs::write(dir.join(Scope::Guild(2).file_name()), "{").unwrap();
+        rules.initial = vec!["edited".to_string()];
+        write(Scope::Guild(1), &rules);
+
+        let (rulesets, warnings) = rulesets.reload_dir(&dir).unwrap();
+        assert_eq!(1, warnings.len());
+        assert_eq!("edited", rulesets.get(Some(1), 10).unwrap().greeting());
+        assert_eq!("guild", rulesets.get(Some(2), 20).unwrap().greeting());
+    }
+}
@@ -0,0 +1,415 @@
+use crate::rules::ParseError;
+use serde::de::Error;
+use serde_json::{json, Map, Value};
+
+/// Version of the rules format understood by this program. Rule files without
+/// a `version` field are considered to be version 1.
+pub const CURRENT_VERSION: u64 = 2;
+
+/// Name of the exported JSON Schema, which is not a rule file.
+pub const SCHEMA_FILE: &str = "schema.json";
+
+/// Upgrades rules by one version, adding the fields it drops to the list.
+type Migration = fn(&mut Map<String, Value>, &mut Vec<String>);
+
+/// `MIGRATIONS[n]` upgrades rules from version `n + 1` to version `n + 2`.
+const MIGRATIONS: [Migration; 1] = [v1_to_v2];
+
+/// Upgrades raw rules to the current version of the format. Also returns the
+/// fields the upgrade dropped, such as `key[0].comment`, so that callers can
+/// warn about them.
+pub fn migrate(mut value: Value) -> Result<(Value, Vec<String>), ParseError> {
+    let mut dropped = Vec::new();
+    let object = match value.as_object_mut() {
+        Some(object) => object,
+        // Let serde report the error.
+        None => return Ok((value, dropped)),
+    };
+
+    let version = match object.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| ParseError::Json(serde_json::Error::custom("invalid version")))?,
+    };
+
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(object, &mut dropped);
+    }
+
+    object.insert("version".to_string(), json!(CURRENT_VERSION));
+    Ok((value, dropped))
+}
+
+/// Version 1 silently ignored unknown fields, while version 2 rejects them:
+/// drops them so that old files keep loading.
+fn v1_to_v2(rules: &mut Map<String, Value>, dropped: &mut Vec<String>) {
+    fn retain(
+        object: &mut Map<String, Value>,
+        path: &str,
+        fields: &[&str],
+        dropped: &mut Vec<String>,
+    ) {
+        object.retain(|k, _| {
+            let known = fields.contains(&k.as_str());
+
+            if !known {
+                dropped.push(format!("{}{}", path, k));
+            }
+
+            known
+        });
+    }
+
+    /// Retains `fields` in the objects of the `list` array of `parent`, and
+    /// returns these objects along with their paths.
+    fn retain_all<'a>(
+        parent: &'a mut Map<String, Value>,
+        path: &str,
+        list: &str,
+        fields: &[&str],
+        dropped: &mut Vec<String>,
+    ) -> Vec<(String, &'a mut Map<String, Value>)> {
+        let items = match parent.get_mut(list).and_then(Value::as_array_mut) {
+            Some(items) => items,
+            None => return vec![],
+        };
+
+        items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                let object = item.as_object_mut()?;
+                let path = format!("{}{}[{}].", path, list, i);
+                retain(object, &path, fields, dropped);
+                Some((path, object))
+            })
+            .collect()
+    }
+
+    let fields = [
+        "$schema", "version", "extends", "include", "initial", "final", "quit", "pre", "post",
+        "synon", "key",
+    ];
+    retain(rules, "", &fields, dropped);
+
+    retain_all(rules, "", "pre", &["in", "out"], dropped);
+    retain_all(rules, "", "post", &["in", "out"], dropped);
+    retain_all(rules, "", "synon", &["label", "list"], dropped);
+
+    let keyword_fields = ["word", "weight", "decomp", "merge", "examples"];
+    let decomposition_fields = ["pattern", "reasmb", "examples"];
+
+    for (path, keyword) in retain_all(rules, "", "key", &keyword_fields, dropped) {
+        retain_all(keyword, &path, "decomp", &decomposition_fields, dropped);
+    }
+}
+
+/// Returns a JSON Schema describing the current rules format, which editors
+/// can use to validate and complete rule files.
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Eliza rules",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "$schema": { "type": "string" },
+            "version": {
+                "description": "Version of the rules format.",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": CURRENT_VERSION
+            },
+            "extends": {
+                "description": "Rule files this one builds on, relative to this file.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "include": {
+                "description": "Alias of `extends`.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "initial": {
+                "description": "Greetings, one of which is chosen at random.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "final": {
+                "description": "Farewells, one of which is chosen at random.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "quit": {
+                "description": "Inputs which end the conversation.",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "pre": {
+                "description": "Word replacements applied to the input.",
+                "type": "array",
+                "items": { "$ref": "#/definitions/replacement" }
+            },
+            "post": {
+                "description": "Word replacements applied to the matched groups.",
+                "type": "array",
+                "items": { "$ref": "#/definitions/replacement" }
+            },
+            "synon": {
+                "type": "array",
+                "items": { "$ref": "#/definitions/synonym" }
+            },
+            "key": {
+                "type": "array",
+                "items": { "$ref": "#/definitions/keyword" }
+            }
+        },
+        "definitions": {
+            "replacement": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["in", "out"],
+                "properties": {
+                    "in": { "type": "string" },
+                    "out": { "type": "string" }
+                }
+            },
+            "synonym": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["label", "list"],
+                "properties": {
+                    "label": { "type": "string", "pattern": "^@" },
+                    "list": { "type": "array", "items": { "type": "string" } }
+                }
+            },
+            "keyword": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["word", "weight", "decomp"],
+                "properties": {
+                    "word": { "type": "string" },
+                    "weight": { "type": "integer", "minimum": 0, "maximum": 255 },
+                    "merge": {
+                        "description": "How decompositions are merged into an existing keyword.",
+                        "enum": ["append", "replace"]
+                    },
+                    "decomp": {
+                        "type": "array",
+                        "items": { "$ref": "#/definitions/decomposition" }
+                    },
+                    "examples": {
+                        "description": "Inputs which should be answered by this keyword.",
+                        "type": "array",
+                        "items": { "$ref": "#/definitions/example" }
+                    }
+                }
+            },
+            "decomposition": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["pattern", "reasmb"],
+                "properties": {
+                    "pattern": { "type": "string" },
+                    "reasmb": { "type": "array", "items": { "type": "string" } },
+                    "examples": {
+                        "description": "Inputs which should be answered by this decomposition.",
+                        "type": "array",
+                        "items": { "$ref": "#/definitions/example" }
+                    }
+                }
+            },
+            "example": {
+                "description": "An input, or an input and the groups its pattern should capture.",
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["input", "groups"],
+                        "properties": {
+                            "input": { "type": "string" },
+                            "groups": { "type": "array", "items": { "type": "string" } }
+                        }
+                    }
+                ]
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{Decomposition, Example, Keyword, MergeMode, Replacement, Rules, Synonym};
+    use std::fs;
+
+    #[test]
+    fn test_migrate_v1() {
+        let value = json!({
+            "initial": ["hi"],
+            "comment": "ignored",
+            "key": [{ "word": "a", "weight": 0, "decomp": [{ "pattern": "*", "reasmb": [], "x": 1 }] }]
+        });
+
+        let expected = json!({
+            "version": CURRENT_VERSION,
+            "initial": ["hi"],
+            "key": [{ "word": "a", "weight": 0, "decomp": [{ "pattern": "*", "reasmb": [] }] }]
+        });
+
+        let (migrated, dropped) = migrate(value).unwrap();
+        assert_eq!(expected, migrated);
+        assert_eq!(vec!["comment", "key[0].decomp[0].x"], dropped);
+    }
+
+    #[test]
+    fn test_migrate_v1_keeps_schema() {
+        let value = json!({ "$schema": SCHEMA_FILE, "initial": ["hi"] });
+        let (migrated, dropped) = migrate(value).unwrap();
+
+        assert_eq!(json!(SCHEMA_FILE), migrated["$schema"]);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_v1_keeps_examples() {
+        let rules: Rules = r#"{
+            "initial": ["hi"],
+            "key": [{
+                "word": "sorry",
+                "weight": 0,
+                "examples": ["sorry"],
+                "decomp": [{ "pattern": "*", "reasmb": ["Never mind."], "examples": ["i am sorry"] }]
+            }]
+        }"#
+        .parse()
+        .unwrap();
+
+        let keyword = &rules.keywords[0];
+        assert_eq!(vec![Example::Input("sorry".to_string())], keyword.examples);
+        assert_eq!(
+            vec![Example::Input("i am sorry".to_string())],
+            keyword.decomposition[0].examples
+        );
+    }
+
+    #[test]
+    fn test_migrate_current_keeps_unknown_fields() {
+        let value = json!({ "version": CURRENT_VERSION, "comment": "kept" });
+        assert_eq!((value.clone(), vec![]), migrate(value).unwrap());
+    }
+
+    #[test]
+    fn test_migrate_unsupported_version() {
+        match migrate(json!({ "version": CURRENT_VERSION + 1 })) {
+            Err(ParseError::UnsupportedVersion(version)) => {
+                assert_eq!(CURRENT_VERSION + 1, version)
+            }
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+    }
+
+    /// Checks that every field of `value` is described by `schema`.
+    fn assert_described(value: &Value, schema: &Value, path: &str) {
+        let schema = match schema["$ref"].as_str() {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/definitions/");
+                &json_schema()["definitions"][name]
+            }
+            None => schema,
+        };
+
+        if let Some(variants) = schema["oneOf"].as_array() {
+            let variant = variants
+                .iter()
+                .find(|variant| variant["type"] == "object")
+                .filter(|_| value.is_object())
+                .unwrap_or(&variants[0]);
+            return assert_described(value, variant, path);
+        }
+
+        match value {
+            Value::Object(object) => {
+                for (field, value) in object {
+                    let path = format!("{}.{}", path, field);
+                    let property = &schema["properties"][field];
+                    assert!(!property.is_null(), "{} is missing from the schema", path);
+                    assert_described(value, property, &path);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    assert_described(item, &schema["items"], &format!("{}[]", path));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_schema_describes_every_field() {
+        let decomposition = Decomposition {
+            pattern: "* you *".to_string(),
+            reasmb: vec!["What about me?".to_string()],
+            examples: vec![
+                Example::Input("you are nice".to_string()),
+                Example::Groups {
+                    input: "i think you are nice".to_string(),
+                    groups: vec!["i think".to_string(), "are nice".to_string()],
+                },
+            ],
+        };
+
+        let rules = Rules {
+            schema: Some(SCHEMA_FILE.to_string()),
+            version: CURRENT_VERSION,
+            extends: vec!["base.json".to_string()],
+            initial: vec!["Hello.".to_string()],
+            final_: vec!["Goodbye.".to_string()],
+            quit: vec!["bye".to_string()],
+            pre: vec![Replacement {
+                from: "dont".to_string(),
+                to: "don't".to_string(),
+            }],
+            post: vec![Replacement {
+                from: "me".to_string(),
+                to: "you".to_string(),
+            }],
+            synonyms: vec![Synonym {
+                label: "@happy".to_string(),
+                list: vec!["glad".to_string()],
+            }],
+            keywords: vec![Keyword {
+                word: "you".to_string(),
+                weight: 1,
+                decomposition: vec![decomposition],
+                merge: MergeMode::Replace,
+                examples: vec![Example::Input("you".to_string())],
+            }],
+        };
+
+        assert_described(
+            &serde_json::to_value(&rules).unwrap(),
+            &json_schema(),
+            "rules",
+        );
+    }
+
+    #[test]
+    fn test_exported_schema_is_up_to_date() {
+        let exported: Value =
+            serde_json::from_str(&fs::read_to_string(format!("rules/{}", SCHEMA_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(
+            json_schema(),
+            exported,
+            "run `eliza schema rules/schema.json`"
+        );
+    }
+}
@@ -0,0 +1,621 @@
+use eliza::chat::{Event, Sessions};
+use eliza::{Eliza, Response, Rules, ScriptRegistry, Trace};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Server as HttpServer};
+
+/// Largest request body accepted, enough for big rule files.
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// Number of threads answering requests.
+const WORKERS: usize = 4;
+
+/// How long a session lasts without messages.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Number of sessions above which new ones are refused.
+const MAX_SESSIONS: usize = 10_000;
+
+/// Number of scripts which can be uploaded on top of the loaded ones.
+const MAX_UPLOADS: usize = 100;
+
+/// The JSON API of `eliza serve`:
+///
+/// - `GET /scripts` lists the scripts, and the default one.
+/// - `PUT /scripts/<name>` adds or replaces a script with the rules of the body.
+/// - `POST /validate` checks the rules of the body without loading them.
+/// - `POST /sessions` starts a conversation, with the script named by the
+///   optional `{"script": ...}` body, and returns its id and greeting.
+/// - `POST /sessions/<id>/messages` answers `{"text": ..., "trace": false}`.
+/// - `DELETE /sessions/<id>` ends a conversation.
+///
+/// Errors are returned as `{"error": ...}`. Sessions end after
+/// `IDLE_TIMEOUT`, and there are at most `MAX_SESSIONS` of them, and
+/// `MAX_UPLOADS` uploaded scripts.
+pub struct Server {
+    scripts: RwLock<ScriptRegistry>,
+    sessions: Sessions,
+    /// The Eliza of each session, a copy of its script so that sessions do
+    /// not share the state of its choices.
+    conversations: Mutex<HashMap<String, Arc<Eliza>>>,
+    max_sessions: usize,
+    max_scripts: usize,
+    /// Seed of the uploaded scripts, to match the loaded ones.
+    seed: Option<u64>,
+    /// Whether messages are traced when they do not say.
+    trace: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NewSession {
+    script: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewMessage {
+    text: String,
+    trace: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageReply<'a> {
+    #[serde(flatten)]
+    response: &'a Response,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<&'a Trace>,
+}
+
+/// A status code and its JSON body, `None` for `204 No Content`.
+type Reply = (u16, Option<Value>);
+
+impl Server {
+    pub fn new(scripts: ScriptRegistry, seed: Option<u64>, trace: bool) -> Self {
+        Server {
+            max_scripts: scripts.names().count() + MAX_UPLOADS,
+            scripts: RwLock::new(scripts),
+            sessions: Sessions::with_idle_timeout(IDLE_TIMEOUT),
+            conversations: Mutex::new(HashMap::new()),
+            max_sessions: MAX_SESSIONS,
+            seed,
+            trace,
+        }
+    }
+
+    /// Answers requests forever, unless the address cannot be listened on.
+    pub fn listen(self, address: &str) -> io::Result<()> {
+        let http = HttpServer::http(address).map_err(io::Error::other)?;
+        println!("Listening on http://{}", http.server_addr());
+        self.run(http)
+    }
+
+    fn run(self, http: HttpServer) -> io::Result<()> {
+        let server = Arc::new(self);
+        let http = Arc::new(http);
+
+        let workers: Vec<_> = (0..WORKERS)
+            .map(|_| {
+                let server = Arc::clone(&server);
+                let http = Arc::clone(&http);
+
+                // A client hanging up, or a failed accept, only loses
+                // that request.
+                thread::spawn(move || loop {
+                    let request = match http.recv() {
+                        Ok(request) => request,
+                        Err(err) => {
+                            eprintln!("Error: could not receive a request: {}", err);
+                            continue;
+                        }
+                    };
+
+                    if let Err(err) = server.respond(request) {
+                        eprintln!("Error: could not send a response: {}", err);
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            worker.join().expect("a worker panicked");
+        }
+
+        Ok(())
+    }
+
+    fn respond(&self, mut request: Request) -> io::Result<()> {
+        let (status, value) = match read_body(request.as_reader())? {
+            Ok(body) => self.handle(request.method(), request.url(), &body),
+            Err(reply) => reply,
+        };
+
+        let json = value.map_or_else(String::new, |value| value.to_string());
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = tiny_http::Response::from_string(json)
+            .with_status_code(status)
+            .with_header(header);
+
+        request.respond(response)
+    }
+
+    /// Routes a request, and returns the status and body of the response.
+    pub fn handle(&self, method: &Method, url: &str, body: &str) -> Reply {
+        let path = url.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["scripts"]) => self.list_scripts(),
+            (Method::Put, ["scripts", name]) => self.upload(name, body),
+            (Method::Post, ["validate"]) => validate(body),
+            (Method::Post, ["sessions"]) => self.open(body),
+            (Method::Post, ["sessions", id, "messages"]) => self.message(id, body),
+            (Method::Delete, ["sessions", id]) => self.close(id),
+            (_, ["scripts"])
+            | (_, ["scripts", _])
+            | (_, ["validate"])
+            | (_, ["sessions"])
+            | (_, ["sessions", _, "messages"])
+            | (_, ["sessions", _]) => error(405, "method not allowed"),
+            _ => error(404, "not found"),
+        }
+    }
+
+    fn list_scripts(&self) -> Reply {
+        let scripts = self.scripts.read().unwrap();
+        let names: Vec<&str> = scripts.names().collect();
+
+        (
+            200,
+            Some(json!({ "scripts": names, "default": scripts.default_name() })),
+        )
+    }
+
+    fn upload(&self, name: &str, body: &str) -> Reply {
+        let is_valid_name = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid_name {
+            return error(
+                400,
+                "script names may only have letters, digits, '-' and '_'",
+            );
+        }
+
+        let rules = match parse(body) {
+            Ok(rules) => rules,
+            Err(reply) => return reply,
+        };
+
+        if let Err(errors) = rules.validate() {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return (
+                422,
+                Some(json!({ "error": "the rules are invalid", "errors": errors })),
+            );
+        }
+
+        let warnings: Vec<String> = rules.warnings().iter().map(|w| w.to_string()).collect();
+        let mut eliza = Eliza::new(rules);
+
+        if let Some(seed) = self.seed {
+            eliza.seed(seed);
+        }
+
+        let mut scripts = self.scripts.write().unwrap();
+        let status = if scripts.contains(name) { 200 } else { 201 };
+
+        if status == 201 && scripts.names().count() >= self.max_scripts {
+            return error(409, "too many scripts were uploaded, replace one instead");
+        }
+        scripts.insert(name.to_string(), eliza);
+
+        (
+            status,
+            Some(json!({ "script": name, "warnings": warnings })),
+        )
+    }
+
+    fn open(&self, body: &str) -> Reply {
+        let options: NewSession = if body.trim().is_empty() {
+            NewSession::default()
+        } else {
+            match serde_json::from_str(body) {
+                Ok(options) => options,
+                Err(err) => return error(400, &format!("invalid session: {}", err)),
+            }
+        };
+
+        let scripts = self.scripts.read().unwrap();
+        let name = match options.script.as_deref().or_else(|| scripts.default_name()) {
+            Some(name) if scripts.contains(name) => name.to_string(),
+            Some(name) => return error(404, &format!("unknown script '{}'", name)),
+            None => return error(404, "no script is loaded"),
+        };
+
+        let mut conversations = self.conversations.lock().unwrap();
+
+        if conversations.len() >= self.max_sessions {
+            // Forget the sessions which ended by being idle first.
+            conversations.retain(|id, _| self.sessions.is_active(id));
+
+            if conversations.len() >= self.max_sessions {
+                return error(503, "too many sessions, try again later");
+            }
+        }
+
+        // Random, so that clients cannot guess the sessions of others.
+        let id = format!("{:016x}", rand::random::<u64>());
+        let eliza = Arc::new(scripts.get(&name).unwrap().clone());
+        let reply = self
+            .sessions
+            .handle(&eliza, Event::Open(id.clone()))
+            .expect("opening a session always greets");
+
+        conversations.insert(id.clone(), eliza);
+
+        (
+            201,
+            Some(json!({ "session": id, "script": name, "greeting": reply.text })),
+        )
+    }
+
+    fn message(&self, id: &str, body: &str) -> Reply {
+        let message: NewMessage = match serde_json::from_str(body) {
+            Ok(message) => message,
+            Err(err) => return error(400, &format!("invalid message: {}", err)),
+        };
+
+        let eliza = match self.conversations.lock().unwrap().get(id) {
+            Some(eliza) if self.sessions.is_active(id) => Arc::clone(eliza),
+            _ => return error(404, &format!("unknown session '{}'", id)),
+        };
+
+        let traced = message.trace.unwrap_or(self.trace);
+        let event = Event::Message {
+            conversation: id.to_string(),
+            text: message.text,
+        };
+
+        let reply = match self.sessions.handle(&eliza, event) {
+            Some(reply) => reply,
+            None => return error(400, "the message is empty"),
+        };
+
+        if reply.is_farewell {
+            self.conversations.lock().unwrap().remove(id);
+        }
+
+        let trace = reply.trace.as_ref().unwrap();
+        let body = MessageReply {
+            response: &trace.response,
+            trace: Some(trace).filter(|_| traced),
+        };
+
+        (200, Some(serde_json::to_value(body).unwrap()))
+    }
+
+    fn close(&self, id: &str) -> Reply {
+        let eliza = match self.conversations.lock().unwrap().remove(id) {
+            Some(eliza) => eliza,
+            None => return error(404, &format!("unknown session '{}'", id)),
+        };
+
+        self.sessions.handle(&eliza, Event::Close(id.to_string()));
+
+        (204, None)
+    }
+}
+
+/// Checks rules like `eliza check`, without loading them.
+fn validate(body: &str) -> Reply {
+    let rules = match parse(body) {
+        Ok(rules) => rules,
+        Err(reply) => return reply,
+    };
+
+    let errors: Vec<String> = match rules.validate() {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+    };
+
+    let examples: Vec<String> = if errors.is_empty() {
+        rules
+            .check_examples()
+            .iter()
+            .map(|f| f.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let warnings: Vec<String> = rules.warnings().iter().map(|w| w.to_string()).collect();
+
+    (
+        200,
+        Some(json!({
+            "valid": errors.is_empty() && examples.is_empty(),
+            "errors": errors,
+            "examples": examples,
+            "warnings": warnings,
+        })),
+    )
+}
+
+/// Reads the body of a request, or returns the error to answer if it is too
+/// large or not text. Bodies without a length are read too.
+fn read_body<R: Read>(reader: R) -> io::Result<Result<String, Reply>> {
+    let mut body = Vec::new();
+    reader.take(MAX_BODY + 1).read_to_end(&mut body)?;
+
+    if body.len() as u64 > MAX_BODY {
+        let message = format!("the body is larger than {} bytes", MAX_BODY);
+        return Ok(Err(error(413, &message)));
+    }
+
+    Ok(String::from_utf8(body).map_err(|_| error(400, "the body is not valid UTF-8")))
+}
+
+/// Reads the rules of a request. `extends` cannot be resolved, as there is no
+/// file to resolve it from.
+fn parse(body: &str) -> Result<Rules, Reply> {
+    let rules: Rules = body
+        .parse()
+        .map_err(|err| error(400, &format!("could not parse the rules: {}", err)))?;
+
+    if !rules.extends.is_empty() {
+        return Err(error(400, "'extends' is not supported in uploaded rules"));
+    }
+
+    Ok(rules)
+}
+
+fn error(status: u16, message: &str) -> Reply {
+    (status, Some(json!({ "error": message })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    const RULES: &str = r#"{
+        "version": 2,
+        "initial": ["Hi."],
+        "final": ["Bye."],
+        "quit": ["bye"],
+        "key": [
+            {
+                "word": "xnone",
+                "weight": 0,
+                "decomp": [{ "pattern": "*", "reasmb": ["Go on."] }]
+            }
+        ]
+    }"#;
+
+    fn server() -> Server {
+        let (rules, _) = Rules::load("rules/therapist.json").unwrap();
+        let mut eliza = Eliza::new(rules);
+        eliza.cycle();
+
+        let mut scripts = ScriptRegistry::new();
+        scripts.insert("therapist".to_string(), eliza);
+        Server::new(scripts, None, false)
+    }
+
+    fn open(server: &Server, body: &str) -> (String, Value) {
+        let (status, value) = server.handle(&Method::Post, "/sessions", body);
+        assert_eq!(201, status);
+
+        let value = value.unwrap();
+        (value["session"].as_str().unwrap().to_string(), value)
+    }
+
+    #[test]
+    fn test_session() {
+        let server = server();
+        let (id, session) = open(&server, "");
+
+        assert_eq!("therapist", session["script"]);
+        assert_eq!(
+            "How do you do.  Please tell me your problem.",
+            session["greeting"]
+        );
+
+        let url = format!("/sessions/{}/messages", id);
+        let (status, reply) = server.handle(&Method::Post, &url, r#"{"text": "  sorry "}"#);
+        assert_eq!(200, status);
+        assert_eq!(
+            json!({ "message": "Please don't apologise.", "is_farewell": false }),
+            reply.unwrap()
+        );
+
+        let (_, reply) = server.handle(&Method::Post, &url, r#"{"text": "sorry", "trace": true}"#);
+        let reply = reply.unwrap();
+        assert_eq!("Apologies are not necessary.", reply["message"]);
+        assert_eq!("sorry", reply["trace"]["keyword"]);
+
+        let (_, reply) = server.handle(&Method::Post, &url, r#"{"text": "bye"}"#);
+        assert_eq!(true, reply.unwrap()["is_farewell"]);
+
+        // The farewell ended the session.
+        let (status, _) = server.handle(&Method::Post, &url, r#"{"text": "hello"}"#);
+        assert_eq!(404, status);
+    }
+
+    #[test]
+    fn test_sessions_are_independent() {
+        let server = server();
+        let message = |id: &str| {
+            let url = format!("/sessions/{}/messages", id);
+            let (_, reply) = server.handle(&Method::Post, &url, r#"{"text": "sorry"}"#);
+            reply.unwrap()["message"].clone()
+        };
+
+        let (first, _) = open(&server, "");
+        assert_eq!("Please don't apologise.", message(&first));
+
+        // Another session starts from the first response too.
+        let (second, _) = open(&server, "");
+        assert_eq!("Please don't apologise.", message(&second));
+        assert_eq!("Apologies are not necessary.", message(&first));
+    }
+
+    #[test]
+    fn test_close() {
+        let server = server();
+        let (id, _) = open(&server, r#"{"script": "therapist"}"#);
+        let url = format!("/sessions/{}", id);
+
+        assert_eq!((204, None), server.handle(&Method::Delete, &url, ""));
+        assert_eq!(404, server.handle(&Method::Delete, &url, "").0);
+
+        let url = format!("/sessions/{}/messages", id);
+        assert_eq!(
+            404,
+            server.handle(&Method::Post, &url, r#"{"text": "hi"}"#).0
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut server = server();
+        server.max_sessions = 2;
+        server.max_scripts = 2;
+
+        let (id, _) = open(&server, "");
+        open(&server, "");
+        assert_eq!(503, server.handle(&Method::Post, "/sessions", "").0);
+
+        let url = format!("/sessions/{}/messages", id);
+        server.handle(&Method::Post, &url, r#"{"text": "bye"}"#);
+        open(&server, "");
+
+        assert_eq!(201, server.handle(&Method::Put, "/scripts/tutor", RULES).0);
+        assert_eq!(409, server.handle(&Method::Put, "/scripts/other", RULES).0);
+        assert_eq!(200, server.handle(&Method::Put, "/scripts/tutor", RULES).0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let server = server();
+        let (id, _) = open(&server, "");
+        let url = format!("/sessions/{}/messages", id);
+
+        let status = |method, url: &str, body| server.handle(&method, url, body).0;
+
+        assert_eq!(404, status(Method::Get, "/nowhere", ""));
+        assert_eq!(405, status(Method::Get, "/sessions", ""));
+        assert_eq!(
+            404,
+            status(Method::Post, "/sessions", r#"{"script": "tutor"}"#)
+        );
+        assert_eq!(
+            400,
+            status(Method::Post, "/sessions", r#"{"color": "blue"}"#)
+        );
+        assert_eq!(400, status(Method::Post, &url, r#"{"text": "   "}"#));
+        assert_eq!(400, status(Method::Post, &url, "hello"));
+        assert_eq!(
+            404,
+            status(Method::Post, "/sessions/0/messages", r#"{"text": "hi"}"#)
+        );
+    }
+
+    #[test]
+    fn test_scripts() {
+        let server = server();
+
+        assert_eq!(
+            (
+                200,
+                Some(json!({ "scripts": ["therapist"], "default": "therapist" }))
+            ),
+            server.handle(&Method::Get, "/scripts", "")
+        );
+
+        assert_eq!(201, server.handle(&Method::Put, "/scripts/tutor", RULES).0);
+        assert_eq!(200, server.handle(&Method::Put, "/scripts/tutor", RULES).0);
+        assert_eq!(400, server.handle(&Method::Put, "/scripts/a.b", RULES).0);
+        assert_eq!(400, server.handle(&Method::Put, "/scripts/tutor", "{").0);
+
+        let invalid = RULES.replace(r#"["Hi."]"#, "[]");
+        let (status, reply) = server.handle(&Method::Put, "/scripts/broken", &invalid);
+        assert_eq!(422, status);
+        assert_eq!(
+            json!(["'initial' has no greetings"]),
+            reply.unwrap()["errors"]
+        );
+
+        let (_, reply) = server.handle(&Method::Get, "/scripts", "");
+        assert_eq!(json!(["therapist", "tutor"]), reply.unwrap()["scripts"]);
+
+        let (_, session) = open(&server, r#"{"script": "tutor"}"#);
+        assert_eq!("Hi.", session["greeting"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let server = server();
+
+        let (status, reply) = server.handle(&Method::Post, "/validate", RULES);
+        assert_eq!(200, status);
+        assert_eq!(true, reply.unwrap()["valid"]);
+
+        let invalid = RULES.replace(r#"["Bye."]"#, "[]");
+        let reply = server
+            .handle(&Method::Post, "/validate", &invalid)
+            .1
+            .unwrap();
+        assert_eq!(false, reply["valid"]);
+        assert_eq!(json!(["'final' has no farewells"]), reply["errors"]);
+
+        // Validating does not load the rules.
+        let (_, reply) = server.handle(&Method::Get, "/scripts", "");
+        assert_eq!(json!(["therapist"]), reply.unwrap()["scripts"]);
+    }
+
+    #[test]
+    fn test_read_body() {
+        let body = |bytes: &[u8]| read_body(bytes).unwrap().map_err(|(status, _)| status);
+
+        assert_eq!(Ok("{}".to_string()), body(b"{}"));
+        assert_eq!(Err(400), body(&[b'"', 0xc3]));
+
+        let large = vec![b' '; MAX_BODY as usize + 1];
+        assert_eq!(Err(413), body(&large));
+        assert!(body(&large[1..]).is_ok());
+    }
+
+    #[test]
+    fn test_listen() {
+        let http = HttpServer::http("127.0.0.1:0").unwrap();
+        let address = http.server_addr().to_ip().unwrap();
+        thread::spawn(move || server().run(http));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        let body = r#"{"script": "therapist"}"#;
+        write!(
+            stream,
+            "POST /sessions HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            address,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+        assert!(response.contains("Content-Type: application/json"));
+        assert!(response.contains(r#""greeting":"How do you do.  Please tell me your problem.""#));
+    }
+}
@@ -0,0 +1,390 @@
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Base URL of the Discord REST API.
+const API: &str = "https://discord.com/api/v8";
+
+/// Permissions of a guild member, see the `permissions` field of
+/// interactions.
+const ADMINISTRATOR: u64 = 1 << 3;
+const MANAGE_GUILD: u64 = 1 << 5;
+
+/// The `/eliza` command and its subcommands, as registered with Discord.
+pub fn definition() -> Value {
+    let message = json!({
+        "type": 3,
+        "name": "message",
+        "description": "What to say to Eliza",
+        "required": true,
+    });
+
+    json!({
+        "name": "eliza",
+        "description": "Talk with Eliza",
+        "options": [
+            {
+                "type": 1,
+                "name": "talk",
+                "description": "Talk with Eliza",
+                "options": [message],
+            },
+            {
+                "type": 1,
+                "name": "load",
+                "description": "Load rules from a URL",
+                "options": [
+                    {
+                        "type": 3,
+                        "name": "url",
+                        "description": "URL of the rules",
+                        "required": true,
+                    },
+                    {
+                        "type": 3,
+                        "name": "scope",
+                        "description": "Where the rules apply (the server by default)",
+                        "choices": [
+                            { "name": "channel", "value": "channel" },
+                            { "name": "global", "value": "global" },
+                        ],
+                    },
+                ],
+            },
+            {
+                "type": 1,
+                "name": "reset",
+                "description": "Go back to the default script in this channel",
+            },
+            {
+                "type": 1,
+                "name": "trace",
+                "description": "Show how Eliza chooses its response",
+                "options": [message],
+            },
+        ],
+    })
+}
+
+/// A `/eliza` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    Talk(String),
+    /// The URL of the rules, and `channel` or `global`, if given.
+    Load(String, Option<String>),
+    Reset,
+    Trace(String),
+}
+
+/// An invocation of `/eliza`, as received from the gateway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interaction {
+    pub id: String,
+    pub token: String,
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub user_tag: String,
+    pub roles: Vec<u64>,
+    /// Whether the user has the Manage Server permission.
+    pub can_manage: bool,
+    pub command: SlashCommand,
+}
+
+impl Interaction {
+    /// Reads an `INTERACTION_CREATE` event. Returns `None` if it is not an
+    /// invocation of a known `/eliza` subcommand, or misses a field.
+    pub fn parse(raw: &Value) -> Option<Interaction> {
+        // Application commands, as opposed to pings or components.
+        if raw["type"] != 2 || raw["data"]["name"] != "eliza" {
+            return None;
+        }
+
+        let subcommand = raw["data"]["options"].get(0)?;
+        let option = |name: &str| -> Option<String> {
+            subcommand["options"]
+                .as_array()?
+                .iter()
+                .find(|o| o["name"] == name)?["value"]
+                .as_str()
+                .map(str::to_string)
+        };
+
+        let command = match subcommand["name"].as_str()? {
+            "talk" => SlashCommand::Talk(option("message")?),
+            "load" => SlashCommand::Load(option("url")?, option("scope")),
+            "reset" => SlashCommand::Reset,
+            "trace" => SlashCommand::Trace(option("message")?),
+            _ => return None,
+        };
+
+        // Members in guilds, users in direct messages.
+        let member = &raw["member"];
+        let user = if member.is_null() {
+            &raw["user"]
+        } else {
+            &member["user"]
+        };
+
+        let permissions: u64 = member["permissions"]
+            .as_str()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0);
+
+        Some(Interaction {
+            id: raw["id"].as_str()?.to_string(),
+            token: raw["token"].as_str()?.to_string(),
+            guild_id: snowflake(&raw["guild_id"]),
+            channel_id: snowflake(&raw["channel_id"])?,
+            user_id: snowflake(&user["id"])?,
+            user_tag: format!(
+                "{}#{}",
+                user["username"].as_str()?,
+                user["discriminator"].as_str().unwrap_or("0000")
+            ),
+            roles: member["roles"]
+                .as_array()
+                .map(|roles| roles.iter().filter_map(snowflake).collect())
+                .unwrap_or_default(),
+            can_manage: member.is_null() || permissions & (ADMINISTRATOR | MANAGE_GUILD) != 0,
+            command,
+        })
+    }
+}
+
+/// Discord ids are sent as strings.
+fn snowflake(value: &Value) -> Option<u64> {
+    value.as_str()?.parse().ok()
+}
+
+/// Registers the commands and answers the interactions, through the REST
+/// API.
+pub struct SlashClient {
+    client: Client,
+    /// `Bot <token>`.
+    authorization: String,
+    application_id: u64,
+}
+
+impl SlashClient {
+    pub fn new(token: &str, application_id: u64) -> Self {
+        let authorization = if token.starts_with("Bot ") {
+            token.to_string()
+        } else {
+            format!("Bot {}", token)
+        };
+
+        SlashClient {
+            client: Client::new(),
+            authorization,
+            application_id,
+        }
+    }
+
+    /// Registers `/eliza` in a guild, where it is available at once, or
+    /// globally, where it may take up to an hour to appear.
+    pub async fn register(&self, guild_id: Option<u64>) -> Result<(), SlashError> {
+        let url = match guild_id {
+            Some(guild_id) => format!(
+                "{}/applications/{}/guilds/{}/commands",
+                API, self.application_id, guild_id
+            ),
+            None => format!("{}/applications/{}/commands", API, self.application_id),
+        };
+
+        self.send(self.client.post(&url).json(&definition())).await
+    }
+
+    /// Answers an interaction right away.
+    pub async fn respond(
+        &self,
+        interaction: &Interaction,
+        content: &str,
+    ) -> Result<(), SlashError> {
+        let body = json!({ "type": 4, "data": { "content": content } });
+        self.send(self.client.post(&callback_url(interaction)).json(&body))
+            .await
+    }
+
+    /// Tells Discord that the answer will take a while, see `edit_response`.
+    pub async fn defer(&self, interaction: &Interaction) -> Result<(), SlashError> {
+        let body = json!({ "type": 5 });
+        self.send(self.client.post(&callback_url(interaction)).json(&body))
+            .await
+    }
+
+    /// Replaces the answer to an interaction, once deferred.
+    pub async fn edit_response(
+        &self,
+        interaction: &Interaction,
+        content: &str,
+    ) -> Result<(), SlashError> {
+        let url = format!(
+            "{}/webhooks/{}/{}/messages/@original",
+            API, self.application_id, interaction.token
+        );
+
+        self.send(self.client.patch(&url).json(&json!({ "content": content })))
+            .await
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<(), SlashError> {
+        let response = request
+            .header("Authorization", &self.authorization)
+            .send()
+            .await
+            .map_err(SlashError::Request)?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            Err(SlashError::Status(status, body))
+        }
+    }
+}
+
+fn callback_url(interaction: &Interaction) -> String {
+    format!(
+        "{}/interactions/{}/{}/callback",
+        API, interaction.id, interaction.token
+    )
+}
+
+#[derive(Debug)]
+pub enum SlashError {
+    Request(reqwest::Error),
+    /// The status of the response, and its body.
+    Status(u16, String),
+}
+
+impl fmt::Display for SlashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SlashError::Request(e) => write!(f, "the request failed: {}", e),
+            SlashError::Status(status, body) => write!(f, "Discord answered {}: {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for SlashError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(subcommand: Value) -> Value {
+        json!({
+            "id": "100",
+            "token": "abc",
+            "type": 2,
+            "guild_id": "1",
+            "channel_id": "10",
+            "member": {
+                "user": { "id": "42", "username": "ada", "discriminator": "0001" },
+                "roles": ["7"],
+                "permissions": "32",
+            },
+            "data": { "name": "eliza", "options": [subcommand] },
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let raw = interaction(json!({
+            "type": 1,
+            "name": "talk",
+            "options": [{ "type": 3, "name": "message", "value": "I am sad" }],
+        }));
+
+        let expected = Interaction {
+            id: "100".to_string(),
+            token: "abc".to_string(),
+            guild_id: Some(1),
+            channel_id: 10,
+            user_id: 42,
+            user_tag: "ada#0001".to_string(),
+            roles: vec![7],
+            can_manage: true,
+            command: SlashCommand::Talk("I am sad".to_string()),
+        };
+
+        assert_eq!(Some(expected), Interaction::parse(&raw));
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let command = |subcommand| Interaction::parse(&interaction(subcommand)).map(|i| i.command);
+
+        assert_eq!(
+            Some(SlashCommand::Load(
+                "https://example.com/rules.json".to_string(),
+                None
+            )),
+            command(json!({
+                "type": 1,
+                "name": "load",
+                "options": [{ "type": 3, "name": "url", "value": "https://example.com/rules.json" }],
+            }))
+        );
+
+        assert_eq!(
+            Some(SlashCommand::Reset),
+            command(json!({ "type": 1, "name": "reset" }))
+        );
+
+        assert_eq!(
+            Some(SlashCommand::Trace("hello".to_string())),
+            command(json!({
+                "type": 1,
+                "name": "trace",
+                "options": [{ "type": 3, "name": "message", "value": "hello" }],
+            }))
+        );
+
+        // Missing a required option, or unknown.
+        assert_eq!(None, command(json!({ "type": 1, "name": "talk" })));
+        assert_eq!(None, command(json!({ "type": 1, "name": "dance" })));
+    }
+
+    #[test]
+    fn test_parse_direct_message() {
+        let raw = json!({
+            "id": "100",
+            "token": "abc",
+            "type": 2,
+            "channel_id": "10",
+            "user": { "id": "42", "username": "ada", "discriminator": "0001" },
+            "data": { "name": "eliza", "options": [{ "type": 1, "name": "reset" }] },
+        });
+
+        let interaction = Interaction::parse(&raw).unwrap();
+        assert_eq!(None, interaction.guild_id);
+        assert_eq!(42, interaction.user_id);
+        assert!(interaction.can_manage);
+    }
+
+    #[test]
+    fn test_parse_ignores_other_interactions() {
+        let mut raw = interaction(json!({ "type": 1, "name": "reset" }));
+        raw["data"]["name"] = json!("other");
+        assert_eq!(None, Interaction::parse(&raw));
+
+        raw["type"] = json!(1);
+        assert_eq!(None, Interaction::parse(&raw));
+    }
+
+    #[test]
+    fn test_definition_matches_parser() {
+        let definition = definition();
+        let names: Vec<&str> = definition["options"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["name"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(vec!["talk", "load", "reset", "trace"], names);
+    }
+}
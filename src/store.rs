@@ -0,0 +1,191 @@
+use crate::rulesets::Scope;
+use eliza::rules::{ParseError, Rules};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+mod file;
+mod memory;
+mod sqlite;
+
+/// Number of rule sets kept in the history of each scope.
+pub const HISTORY_LENGTH: usize = 20;
+
+/// A rule set stored for a scope, and how it was loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Tag of the user who loaded the rules.
+    pub user: String,
+    /// Where the rules were loaded from: a URL, a file name, or a rollback.
+    pub source: String,
+    pub rules: Rules,
+}
+
+impl Entry {
+    fn new(rules: &Rules, user: &str, source: &str) -> Self {
+        Entry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            user: user.to_string(),
+            source: source.to_string(),
+            rules: rules.clone(),
+        }
+    }
+}
+
+/// Where the discord bot keeps the rules loaded for each scope, and their
+/// previous versions.
+pub trait RuleStore: Send + Sync {
+    /// Returns the current rules of a scope, if any.
+    fn load(&self, scope: Scope) -> Result<Option<Rules>, StoreError>;
+
+    /// Replaces the rules of a scope, and adds them to its history.
+    fn save(&self, scope: Scope, rules: &Rules, user: &str, source: &str)
+        -> Result<(), StoreError>;
+
+    /// Returns the scopes having rules.
+    fn list(&self) -> Result<Vec<Scope>, StoreError>;
+
+    /// Returns the rules stored for a scope, the most recent ones first, up
+    /// to `HISTORY_LENGTH` of them.
+    fn history(&self, scope: Scope) -> Result<Vec<Entry>, StoreError>;
+}
+
+/// Opens the store named by `kind`: `file` (rules stored in the `path`
+/// directory), `sqlite` (in the `path` database) or `memory` (lost on exit).
+pub fn open<P: AsRef<Path>>(kind: &str, path: P) -> Result<Box<dyn RuleStore>, StoreError> {
+    match kind {
+        "file" => Ok(Box::new(FileStore::new(path.as_ref()))),
+        "sqlite" => Ok(Box::new(SqliteStore::open(path)?)),
+        "memory" => Ok(Box::new(MemoryStore::new())),
+        _ => Err(StoreError::UnknownKind(kind.to_string())),
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Parse(ParseError),
+    Sqlite(rusqlite::Error),
+    UnknownKind(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::Json(e) => write!(f, "invalid history: {}", e),
+            StoreError::Parse(e) => write!(f, "invalid rules: {}", e),
+            StoreError::Sqlite(e) => write!(f, "database error: {}", e),
+            StoreError::UnknownKind(kind) => {
+                write!(f, "unknown store '{}' (file, sqlite or memory)", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn rules(greeting: &str) -> Rules {
+        let (mut rules, _) = Rules::from_file("rules/therapist.json").unwrap();
+        rules.initial = vec![greeting.to_string()];
+        rules
+    }
+
+    /// Checks the behaviour shared by every store.
+    fn check(store: &dyn RuleStore) {
+        assert!(store.list().unwrap().is_empty());
+        assert_eq!(None, store.load(Scope::Guild(1)).unwrap());
+        assert!(store.history(Scope::Guild(1)).unwrap().is_empty());
+
+        for i in 0..HISTORY_LENGTH + 2 {
+            let source = format!("https://example.com/{}.json", i);
+            store
+                .save(
+                    Scope::Guild(1),
+                    &rules(&i.to_string()),
+                    "user#0001",
+                    &source,
+                )
+                .unwrap();
+        }
+
+        store
+            .save(Scope::Global, &rules("global"), "admin#0001", "global.json")
+            .unwrap();
+
+        let mut scopes = store.list().unwrap();
+        scopes.sort_by_key(|scope| scope.name());
+        assert_eq!(vec![Scope::Global, Scope::Guild(1)], scopes);
+
+        assert_eq!(Some(rules("21")), store.load(Scope::Guild(1)).unwrap());
+        assert_eq!(Some(rules("global")), store.load(Scope::Global).unwrap());
+
+        let history = store.history(Scope::Guild(1)).unwrap();
+        assert_eq!(HISTORY_LENGTH, history.len());
+        assert_eq!("https://example.com/21.json", history[0].source);
+        assert_eq!("user#0001", history[0].user);
+        assert_eq!(rules("2"), history[HISTORY_LENGTH - 1].rules);
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = TempDir::new("file-store");
+        check(&FileStore::new(dir.to_path_buf()));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let dir = TempDir::new("sqlite-store");
+        check(&SqliteStore::open(dir.join("rules.db")).unwrap());
+    }
+
+    #[test]
+    fn test_open() {
+        assert!(open("memory", "").is_ok());
+
+        match open("redis", "") {
+            Err(StoreError::UnknownKind(kind)) => assert_eq!("redis", kind),
+            _ => panic!("expected an unknown store"),
+        }
+    }
+}
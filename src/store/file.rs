@@ -0,0 +1,184 @@
+use super::{Entry, RuleStore, StoreError, HISTORY_LENGTH};
+use crate::rulesets::Scope;
+use eliza::Rules;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Stores the rules of each scope in a directory, as read by
+/// `Rulesets::reload_dir`, along with the history of their previous versions
+/// in its `history` subdirectory.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FileStore { dir: dir.into() }
+    }
+
+    fn history_path(&self, scope: Scope) -> PathBuf {
+        self.dir.join("history").join(scope.file_name())
+    }
+}
+
+impl RuleStore for FileStore {
+    fn load(&self, scope: Scope) -> Result<Option<Rules>, StoreError> {
+        match fs::read_to_string(self.dir.join(scope.file_name())) {
+            Ok(content) => content.parse().map(Some).map_err(StoreError::Parse),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Files are written next to their destination first, then renamed, so
+    /// the watcher never reads a partially written file.
+    fn save(
+        &self,
+        scope: Scope,
+        rules: &Rules,
+        user: &str,
+        source: &str,
+    ) -> Result<(), StoreError> {
+        let mut history = self.history(scope)?;
+        history.insert(0, Entry::new(rules, user, source));
+        history.truncate(HISTORY_LENGTH);
+
+        fs::create_dir_all(self.dir.join("history"))?;
+        write_atomic(&self.history_path(scope), &serde_json::to_string(&history)?)?;
+        write_atomic(
+            &self.dir.join(scope.file_name()),
+            &serde_json::to_string(rules)?,
+        )?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Scope>, StoreError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut scopes = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if let Some(scope) = path
+                .file_name()
+                .and_then(|n| Scope::from_file_name(&n.to_string_lossy()))
+            {
+                scopes.push(scope);
+            }
+        }
+
+        Ok(scopes)
+    }
+
+    /// The rules of each entry are upgraded like loaded rules, as they may
+    /// have been saved in an older version.
+    fn history(&self, scope: Scope) -> Result<Vec<Entry>, StoreError> {
+        let content = match fs::read_to_string(self.history_path(scope)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entries: Vec<StoredEntry> = serde_json::from_str(&content)?;
+        let mut history = Vec::new();
+
+        for entry in entries {
+            history.push(Entry {
+                timestamp: entry.timestamp,
+                user: entry.user,
+                source: entry.source,
+                rules: entry.rules.to_string().parse().map_err(StoreError::Parse)?,
+            });
+        }
+
+        Ok(history)
+    }
+}
+
+/// An entry of a history file, whose rules are not parsed yet.
+#[derive(Deserialize)]
+struct StoredEntry {
+    timestamp: u64,
+    user: String,
+    source: String,
+    rules: Value,
+}
+
+/// Writes a file through a temporary file renamed over it, which is atomic
+/// on a single filesystem.
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rulesets::Rulesets;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_saved_rules_can_be_loaded_from_the_directory() {
+        let dir = TempDir::new("file-store-dir");
+        let store = FileStore::new(dir.to_path_buf());
+
+        let (mut rules, _) = Rules::from_file("rules/therapist.json").unwrap();
+        rules.initial = vec!["first".to_string()];
+        store
+            .save(Scope::Guild(1), &rules, "user#0001", "first.json")
+            .unwrap();
+        rules.initial = vec!["second".to_string()];
+        store
+            .save(Scope::Guild(1), &rules, "user#0001", "second.json")
+            .unwrap();
+
+        // The history is not mistaken for rules.
+        let (rulesets, warnings) = Rulesets::new().reload_dir(&dir).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!("second", rulesets.get(Some(1), 10).unwrap().greeting());
+    }
+
+    #[test]
+    fn test_history_is_migrated() {
+        let dir = TempDir::new("file-store-history");
+        fs::create_dir_all(dir.join("history")).unwrap();
+
+        // Version 1 rules, with a field version 2 does not know.
+        let history = r#"[{
+            "timestamp": 1,
+            "user": "user#0001",
+            "source": "old.json",
+            "rules": {
+                "comment": "dropped by the migration",
+                "initial": ["hi"],
+                "final": ["bye"],
+                "quit": ["bye"],
+                "pre": [],
+                "post": [],
+                "synon": [],
+                "key": []
+            }
+        }]"#;
+        fs::write(
+            dir.join("history").join(Scope::Guild(1).file_name()),
+            history,
+        )
+        .unwrap();
+
+        let store = FileStore::new(dir.to_path_buf());
+        let history = store.history(Scope::Guild(1)).unwrap();
+        assert_eq!(1, history.len());
+        assert_eq!(vec!["hi".to_string()], history[0].rules.initial);
+    }
+}
@@ -0,0 +1,50 @@
+use super::{Entry, RuleStore, StoreError, HISTORY_LENGTH};
+use crate::rulesets::Scope;
+use eliza::Rules;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Keeps the rules in memory, until the bot stops.
+#[derive(Default)]
+pub struct MemoryStore {
+    histories: Mutex<HashMap<Scope, Vec<Entry>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RuleStore for MemoryStore {
+    fn load(&self, scope: Scope) -> Result<Option<Rules>, StoreError> {
+        let histories = self.histories.lock().unwrap();
+        Ok(histories
+            .get(&scope)
+            .and_then(|history| history.first())
+            .map(|entry| entry.rules.clone()))
+    }
+
+    fn save(
+        &self,
+        scope: Scope,
+        rules: &Rules,
+        user: &str,
+        source: &str,
+    ) -> Result<(), StoreError> {
+        let mut histories = self.histories.lock().unwrap();
+        let history = histories.entry(scope).or_default();
+        history.insert(0, Entry::new(rules, user, source));
+        history.truncate(HISTORY_LENGTH);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Scope>, StoreError> {
+        Ok(self.histories.lock().unwrap().keys().copied().collect())
+    }
+
+    fn history(&self, scope: Scope) -> Result<Vec<Entry>, StoreError> {
+        let histories = self.histories.lock().unwrap();
+        Ok(histories.get(&scope).cloned().unwrap_or_default())
+    }
+}
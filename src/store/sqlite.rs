@@ -0,0 +1,132 @@
+use super::{Entry, RuleStore, StoreError, HISTORY_LENGTH};
+use crate::rulesets::Scope;
+use eliza::Rules;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Stores the rules in a SQLite database, one row per version.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let connection = Connection::open(path)?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS rules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                scope TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                user TEXT NOT NULL,
+                source TEXT NOT NULL,
+                rules TEXT NOT NULL
+            )",
+            params![],
+        )?;
+
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl RuleStore for SqliteStore {
+    fn load(&self, scope: Scope) -> Result<Option<Rules>, StoreError> {
+        let connection = self.connection.lock().unwrap();
+
+        let rules: Option<String> = connection
+            .query_row(
+                "SELECT rules FROM rules WHERE scope = ?1 ORDER BY id DESC LIMIT 1",
+                params![scope.name()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match rules {
+            Some(rules) => rules.parse().map(Some).map_err(StoreError::Parse),
+            None => Ok(None),
+        }
+    }
+
+    fn save(
+        &self,
+        scope: Scope,
+        rules: &Rules,
+        user: &str,
+        source: &str,
+    ) -> Result<(), StoreError> {
+        let entry = Entry::new(rules, user, source);
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        transaction.execute(
+            "INSERT INTO rules (scope, timestamp, user, source, rules) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                scope.name(),
+                entry.timestamp as i64,
+                entry.user,
+                entry.source,
+                serde_json::to_string(rules)?
+            ],
+        )?;
+
+        transaction.execute(
+            "DELETE FROM rules WHERE scope = ?1 AND id NOT IN
+                (SELECT id FROM rules WHERE scope = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![scope.name(), HISTORY_LENGTH as i64],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Scope>, StoreError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT DISTINCT scope FROM rules")?;
+        let names = statement.query_map(params![], |row| row.get::<_, String>(0))?;
+
+        let mut scopes = Vec::new();
+
+        for name in names {
+            if let Some(scope) = Scope::from_name(&name?) {
+                scopes.push(scope);
+            }
+        }
+
+        Ok(scopes)
+    }
+
+    fn history(&self, scope: Scope) -> Result<Vec<Entry>, StoreError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT timestamp, user, source, rules FROM rules WHERE scope = ?1 ORDER BY id DESC",
+        )?;
+
+        let rows = statement.query_map(params![scope.name()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut history = Vec::new();
+
+        for row in rows {
+            let (timestamp, user, source, rules) = row?;
+
+            history.push(Entry {
+                timestamp: timestamp as u64,
+                user,
+                source,
+                rules: rules.parse().map_err(StoreError::Parse)?,
+            });
+        }
+
+        Ok(history)
+    }
+}
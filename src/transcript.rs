@@ -0,0 +1,358 @@
+use crate::eliza::Eliza;
+use crate::rules::{Rules, RulesError};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of transcript files, when looking for them in a directory.
+pub const EXTENSION: &str = "transcript";
+
+/// A conversation, and the rules expected to produce it.
+///
+/// The header gives the rules file, relative to the transcript, and either a
+/// seed or the cycling strategy (see `Eliza::cycle`). After a blank line come
+/// the greeting, if checked, then the messages prefixed with `> `, each
+/// followed by the expected reply, as written by `:save` in `eliza chat`:
+///
+/// ```text
+/// # Lines starting with '#' are comments.
+/// rules: ../../rules/therapist.json
+/// seed: 1
+///
+/// How do you do.  Please tell me your problem.
+/// > hello
+/// Hi.  What seems to be your problem ?
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct Transcript {
+    pub rules: PathBuf,
+    pub strategy: Strategy,
+    /// The line number and the expected greeting.
+    pub greeting: Option<(usize, String)>,
+    pub exchanges: Vec<Exchange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Seed(u64),
+    Cycle,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exchange {
+    /// Line number of the message.
+    pub line: usize,
+    pub input: String,
+    pub reply: String,
+}
+
+/// A reply which differs from the transcript.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    /// The message, or `None` for the greeting.
+    pub input: Option<String>,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.input {
+            Some(input) => writeln!(f, "line {}: > {}", self.line, input)?,
+            None => writeln!(f, "line {}: greeting", self.line)?,
+        }
+
+        writeln!(f, "- {}", self.expected)?;
+        write!(f, "+ {}", self.actual)
+    }
+}
+
+impl Transcript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Transcript, TranscriptError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(TranscriptError::Io)?;
+        Transcript::parse(&contents, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Reads a transcript, whose rules path is relative to `dir`.
+    pub fn parse(contents: &str, dir: &Path) -> Result<Transcript, TranscriptError> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim_end()))
+            .filter(|(_, line)| !line.starts_with('#'));
+
+        let mut rules = None;
+        let mut strategy = None;
+
+        for (number, line) in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+
+            let syntax = |message: &str| TranscriptError::Syntax(number, message.to_string());
+            let mut parts = line.splitn(2, ':');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| syntax("expected 'key: value'"))?;
+            let value = value.trim();
+
+            match key {
+                "rules" => rules = Some(dir.join(value)),
+                "seed" => {
+                    let seed = value.parse().map_err(|_| syntax("invalid seed"))?;
+                    strategy = Some(Strategy::Seed(seed));
+                }
+                "choose" if value == "cycle" => strategy = Some(Strategy::Cycle),
+                "choose" => return Err(syntax("only 'choose: cycle' is supported")),
+                _ => return Err(syntax(&format!("unknown key '{}'", key))),
+            }
+        }
+
+        let missing = |message: &str| TranscriptError::Syntax(0, message.to_string());
+        let rules = rules.ok_or_else(|| missing("missing 'rules: <path>'"))?;
+        let strategy = strategy.ok_or_else(|| missing("missing 'seed: <n>' or 'choose: cycle'"))?;
+
+        let mut greeting = None;
+        let mut exchanges = Vec::new();
+        let mut input: Option<(usize, &str)> = None;
+
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let message = line.strip_prefix("> ").or_else(|| line.strip_prefix('>'));
+
+            match (message, input) {
+                (Some(_), Some((at, _))) => {
+                    return Err(TranscriptError::Syntax(at, "missing reply".to_string()))
+                }
+                (Some(message), None) => input = Some((number, message.trim())),
+                (None, Some((at, message))) => {
+                    exchanges.push(Exchange {
+                        line: at,
+                        input: message.to_string(),
+                        reply: line.to_string(),
+                    });
+                    input = None;
+                }
+                (None, None) if greeting.is_none() && exchanges.is_empty() => {
+                    greeting = Some((number, line.to_string()))
+                }
+                (None, None) => {
+                    return Err(TranscriptError::Syntax(
+                        number,
+                        "expected a message starting with '> '".to_string(),
+                    ))
+                }
+            }
+        }
+
+        if let Some((at, _)) = input {
+            return Err(TranscriptError::Syntax(at, "missing reply".to_string()));
+        }
+
+        Ok(Transcript {
+            rules,
+            strategy,
+            greeting,
+            exchanges,
+        })
+    }
+
+    /// Plays the conversation, and returns the replies which differ from the
+    /// transcript.
+    pub fn run(&self) -> Result<Vec<Mismatch>, TranscriptError> {
+        let (rules, _) = Rules::load(&self.rules).map_err(TranscriptError::Rules)?;
+        let mut eliza = Eliza::new(rules);
+
+        match self.strategy {
+            Strategy::Seed(seed) => eliza.seed(seed),
+            Strategy::Cycle => eliza.cycle(),
+        }
+
+        let mut mismatches = Vec::new();
+
+        // The greeting is always chosen, so that the replies do not depend on
+        // whether it is checked.
+        let greeting = eliza.greeting();
+
+        if let Some((line, expected)) = &self.greeting {
+            if expected != greeting {
+                mismatches.push(Mismatch {
+                    line: *line,
+                    input: None,
+                    expected: expected.clone(),
+                    actual: greeting.to_string(),
+                });
+            }
+        }
+
+        for exchange in &self.exchanges {
+            let actual = eliza.interact(&exchange.input).message;
+
+            if actual != exchange.reply {
+                mismatches.push(Mismatch {
+                    line: exchange.line,
+                    input: Some(exchange.input.clone()),
+                    expected: exchange.reply.clone(),
+                    actual,
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+}
+
+/// Finds the transcripts of files and directories, in alphabetical order
+/// within each directory.
+pub fn find(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut files = Vec::new();
+
+            for entry in fs::read_dir(path)? {
+                let file = entry?.path();
+
+                if file.extension() == Some(EXTENSION.as_ref()) {
+                    files.push(file);
+                }
+            }
+
+            files.sort();
+            found.extend(files);
+        } else {
+            found.push(path.clone());
+        }
+    }
+
+    Ok(found)
+}
+
+#[derive(Debug)]
+pub enum TranscriptError {
+    Io(io::Error),
+    /// The line number, 0 for the whole header, and what is wrong.
+    Syntax(usize, String),
+    Rules(RulesError),
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::Io(e) => write!(f, "could not read the transcript: {}", e),
+            TranscriptError::Syntax(0, message) => write!(f, "{}", message),
+            TranscriptError::Syntax(line, message) => write!(f, "line {}: {}", line, message),
+            TranscriptError::Rules(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let contents = "\
+# A comment.
+rules: rules.json
+seed: 3
+
+Hello.
+> hi
+# Another comment.
+Hello.
+>what
+Why?
+";
+
+        let expected = Transcript {
+            rules: PathBuf::from("dir/rules.json"),
+            strategy: Strategy::Seed(3),
+            greeting: Some((5, "Hello.".to_string())),
+            exchanges: vec![
+                Exchange {
+                    line: 6,
+                    input: "hi".to_string(),
+                    reply: "Hello.".to_string(),
+                },
+                Exchange {
+                    line: 9,
+                    input: "what".to_string(),
+                    reply: "Why?".to_string(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            expected,
+            Transcript::parse(contents, Path::new("dir")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |contents| match Transcript::parse(contents, Path::new("")) {
+            Err(TranscriptError::Syntax(line, _)) => line,
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+
+        assert_eq!(0, error("seed: 1\n\n> hi\nHello.\n"));
+        assert_eq!(0, error("rules: a.json\n\n> hi\nHello.\n"));
+        assert_eq!(2, error("rules: a.json\nseed: one\n"));
+        assert_eq!(2, error("rules: a.json\ncolor: blue\n"));
+        assert_eq!(
+            4,
+            error("rules: a.json\nseed: 1\n\n> hi\n> there\nHello.\n")
+        );
+        assert_eq!(4, error("rules: a.json\nseed: 1\n\n> hi\n"));
+        assert_eq!(
+            6,
+            error("rules: a.json\nseed: 1\n\n> hi\nHello.\nHow are you?\n")
+        );
+    }
+
+    #[test]
+    fn test_run_mismatch() {
+        let contents = "\
+rules: rules/therapist.json
+choose: cycle
+
+> sorry
+Please don't apologise.
+> sorry
+Please don't apologise.
+";
+
+        let transcript = Transcript::parse(contents, Path::new("")).unwrap();
+        let mismatches = transcript.run().unwrap();
+
+        assert_eq!(
+            vec![Mismatch {
+                line: 6,
+                input: Some("sorry".to_string()),
+                expected: "Please don't apologise.".to_string(),
+                actual: "Apologies are not necessary.".to_string(),
+            }],
+            mismatches
+        );
+    }
+
+    #[test]
+    fn test_transcripts() {
+        let files = find(&[PathBuf::from("tests/transcripts")]).unwrap();
+        assert!(!files.is_empty());
+
+        for file in files {
+            let mismatches = Transcript::load(&file).unwrap().run().unwrap();
+            let diff: Vec<String> = mismatches.iter().map(Mismatch::to_string).collect();
+            assert!(diff.is_empty(), "{}:\n{}", file.display(), diff.join("\n"));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::permissions::ids_from_env;
+use std::env;
+
+/// Which messages the discord bot answers.
+#[derive(Debug)]
+pub struct Triggers {
+    pub direct_messages: bool,
+    /// Messages mentioning the bot, wherever the mention is.
+    pub mentions: bool,
+    /// Messages replying to a message of the bot.
+    pub replies: bool,
+    /// Channels where every message is answered.
+    pub channels: Vec<u64>,
+}
+
+/// What the triggers look at in a message.
+#[derive(Debug, Default)]
+pub struct Incoming {
+    pub private: bool,
+    pub mentions_bot: bool,
+    pub replies_to_bot: bool,
+    pub channel: u64,
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Triggers {
+            direct_messages: true,
+            mentions: true,
+            replies: true,
+            channels: vec![],
+        }
+    }
+}
+
+impl Triggers {
+    /// Reads the enabled triggers from `ELIZA_TRIGGERS` (comma-separated
+    /// `dm`, `mention` and `reply`, all of them by default) and the
+    /// dedicated channels from `ELIZA_CHANNELS` (comma-separated ids).
+    pub fn from_env() -> Result<Self, String> {
+        let mut triggers = Triggers {
+            channels: ids_from_env("ELIZA_CHANNELS")?,
+            ..Default::default()
+        };
+
+        if let Ok(names) = env::var("ELIZA_TRIGGERS") {
+            triggers.direct_messages = false;
+            triggers.mentions = false;
+            triggers.replies = false;
+
+            for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match name {
+                    "dm" => triggers.direct_messages = true,
+                    "mention" => triggers.mentions = true,
+                    "reply" => triggers.replies = true,
+                    _ => {
+                        return Err(format!(
+                        "ELIZA_TRIGGERS contains an unknown trigger: '{}' (dm, mention or reply)",
+                        name
+                    ))
+                    }
+                }
+            }
+        }
+
+        Ok(triggers)
+    }
+
+    /// Whether the replied message must be looked up to know if the bot
+    /// should answer. A reply pings its author by default, so a reply to the
+    /// bot usually mentions it too, which only settles it if mentions are
+    /// answered.
+    pub fn checks_reply(&self, mentions_bot: bool) -> bool {
+        self.replies && !(self.mentions && mentions_bot)
+    }
+
+    /// Whether the bot should answer a message.
+    pub fn matches(&self, incoming: &Incoming) -> bool {
+        (self.direct_messages && incoming.private)
+            || (self.mentions && incoming.mentions_bot)
+            || (self.replies && incoming.replies_to_bot)
+            || self.channels.contains(&incoming.channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_triggers() {
+        let triggers = Triggers::default();
+
+        let private = Incoming {
+            private: true,
+            ..Default::default()
+        };
+        let mention = Incoming {
+            mentions_bot: true,
+            ..Default::default()
+        };
+        let reply = Incoming {
+            replies_to_bot: true,
+            ..Default::default()
+        };
+
+        assert!(triggers.matches(&private));
+        assert!(triggers.matches(&mention));
+        assert!(triggers.matches(&reply));
+        assert!(!triggers.matches(&Incoming::default()));
+    }
+
+    #[test]
+    fn test_dedicated_channels() {
+        let triggers = Triggers {
+            direct_messages: false,
+            mentions: false,
+            replies: false,
+            channels: vec![10],
+        };
+
+        let in_channel = Incoming {
+            channel: 10,
+            ..Default::default()
+        };
+        let mention = Incoming {
+            mentions_bot: true,
+            channel: 11,
+            ..Default::default()
+        };
+
+        assert!(triggers.matches(&in_channel));
+        assert!(!triggers.matches(&mention));
+    }
+
+    #[test]
+    fn test_reply_mentioning_bot() {
+        let triggers = Triggers {
+            direct_messages: true,
+            mentions: false,
+            replies: true,
+            channels: vec![],
+        };
+
+        // The reply pings the bot, but mentions are not answered, so only
+        // the replied message tells whether to answer.
+        assert!(triggers.checks_reply(true));
+
+        let reply = Incoming {
+            mentions_bot: true,
+            replies_to_bot: true,
+            ..Default::default()
+        };
+        assert!(triggers.matches(&reply));
+
+        assert!(!Triggers::default().checks_reply(true));
+        assert!(Triggers::default().checks_reply(false));
+    }
+}
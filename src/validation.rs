@@ -0,0 +1,355 @@
+use crate::pattern::group_count;
+use crate::rules::Rules;
+use std::fmt;
+
+/// A problem in a rule set which would make Eliza panic while talking.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    NoInitial,
+    NoFinal,
+    NoXnone,
+    EmptyReasmb {
+        word: String,
+        pattern: String,
+    },
+    UnknownSynonym {
+        word: String,
+        pattern: String,
+        label: String,
+    },
+    InvalidGroup {
+        word: String,
+        pattern: String,
+        template: String,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::NoInitial => write!(f, "'initial' has no greetings"),
+            ValidationError::NoFinal => write!(f, "'final' has no farewells"),
+            ValidationError::NoXnone => {
+                write!(f, "the 'xnone' keyword is missing, or has no decomposition")
+            }
+            ValidationError::EmptyReasmb { word, pattern } => write!(
+                f,
+                "keyword '{}', pattern '{}': 'reasmb' is empty",
+                word, pattern
+            ),
+            ValidationError::UnknownSynonym {
+                word,
+                pattern,
+                label,
+            } => write!(
+                f,
+                "keyword '{}', pattern '{}': unknown synonym '{}'",
+                word, pattern, label
+            ),
+            ValidationError::InvalidGroup {
+                word,
+                pattern,
+                template,
+            } => write!(
+                f,
+                "keyword '{}', pattern '{}': '{}' refers to a missing or invalid group",
+                word, pattern, template
+            ),
+        }
+    }
+}
+
+/// Something in a rule set which is probably a mistake, but does not prevent
+/// Eliza from using it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationWarning {
+    DuplicateKeyword { word: String },
+    UppercaseKeyword { word: String },
+    NoDecomposition { word: String },
+    UnreachableDecomposition { word: String, pattern: String },
+    UnusedSynonym { label: String },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationWarning::DuplicateKeyword { word } => {
+                write!(f, "keyword '{}' is defined several times", word)
+            }
+            ValidationWarning::UppercaseKeyword { word } => write!(
+                f,
+                "keyword '{}' has uppercase letters, but inputs are lowercased",
+                word
+            ),
+            ValidationWarning::NoDecomposition { word } => {
+                write!(f, "keyword '{}' has no decomposition", word)
+            }
+            ValidationWarning::UnreachableDecomposition { word, pattern } => write!(
+                f,
+                "keyword '{}', pattern '{}': a previous pattern always matches first",
+                word, pattern
+            ),
+            ValidationWarning::UnusedSynonym { label } => {
+                write!(f, "synonym '{}' is not used by any pattern", label)
+            }
+        }
+    }
+}
+
+impl Rules {
+    /// Checks that the rules can be used by Eliza, and returns all the
+    /// problems found otherwise.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+
+        if self.initial.is_empty() {
+            errors.push(ValidationError::NoInitial);
+        }
+
+        if self.final_.is_empty() {
+            errors.push(ValidationError::NoFinal);
+        }
+
+        let has_xnone = self
+            .keywords
+            .iter()
+            .any(|k| k.word == "xnone" && !k.decomposition.is_empty());
+
+        if !has_xnone {
+            errors.push(ValidationError::NoXnone);
+        }
+
+        for keyword in &self.keywords {
+            for decomposition in &keyword.decomposition {
+                let pattern = &decomposition.pattern;
+
+                if decomposition.reasmb.is_empty() {
+                    errors.push(ValidationError::EmptyReasmb {
+                        word: keyword.word.clone(),
+                        pattern: pattern.clone(),
+                    });
+                }
+
+                for label in pattern.split(' ').filter(|p| p.starts_with('@')) {
+                    if !self.synonyms.iter().any(|s| s.label == label) {
+                        errors.push(ValidationError::UnknownSynonym {
+                            word: keyword.word.clone(),
+                            pattern: pattern.clone(),
+                            label: label.to_string(),
+                        });
+                    }
+                }
+
+                let groups = group_count(pattern);
+
+                for template in &decomposition.reasmb {
+                    // Eliza only reads the digit right after the parenthesis.
+                    let invalid = template
+                        .split(' ')
+                        .filter(|part| part.starts_with('('))
+                        .any(|part| match part.as_bytes().get(1) {
+                            Some(digit) if digit.is_ascii_digit() => {
+                                let group = (digit - b'0') as usize;
+                                group == 0 || group > groups
+                            }
+                            _ => true,
+                        });
+
+                    if invalid {
+                        errors.push(ValidationError::InvalidGroup {
+                            word: keyword.word.clone(),
+                            pattern: pattern.clone(),
+                            template: template.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Rules {
+    /// Returns the likely mistakes of the rules, see `ValidationWarning`.
+    pub fn warnings(&self) -> Vec<ValidationWarning> {
+        let mut warnings = Vec::new();
+
+        for (i, keyword) in self.keywords.iter().enumerate() {
+            let word = &keyword.word;
+
+            if self.keywords[..i].iter().any(|k| &k.word == word) {
+                warnings.push(ValidationWarning::DuplicateKeyword { word: word.clone() });
+            }
+
+            if word.chars().any(char::is_uppercase) {
+                warnings.push(ValidationWarning::UppercaseKeyword { word: word.clone() });
+            }
+
+            if keyword.decomposition.is_empty() {
+                warnings.push(ValidationWarning::NoDecomposition { word: word.clone() });
+            }
+
+            for (j, decomposition) in keyword.decomposition.iter().enumerate() {
+                let shadowed = keyword.decomposition[..j]
+                    .iter()
+                    .any(|d| d.pattern == "*" || d.pattern == decomposition.pattern);
+
+                if shadowed {
+                    warnings.push(ValidationWarning::UnreachableDecomposition {
+                        word: word.clone(),
+                        pattern: decomposition.pattern.clone(),
+                    });
+                }
+            }
+        }
+
+        for synonym in &self.synonyms {
+            let used = self.keywords.iter().any(|k| {
+                k.decomposition
+                    .iter()
+                    .any(|d| d.pattern.split(' ').any(|p| p == synonym.label))
+            });
+
+            if !used {
+                warnings.push(ValidationWarning::UnusedSynonym {
+                    label: synonym.label.clone(),
+                });
+            }
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Rules {
+        json.parse().unwrap()
+    }
+
+    #[test]
+    fn test_validate_therapist() {
+        let (rules, _) = Rules::from_file("rules/therapist.json").unwrap();
+        assert_eq!(Ok(()), rules.validate());
+    }
+
+    #[test]
+    fn test_warnings() {
+        let rules = parse(
+            r#"{
+                "synon": [{"label": "@be", "list": ["am"]}, {"label": "@happy", "list": ["glad"]}],
+                "key": [
+                    {"word": "Hello", "weight": 0, "decomp": []},
+                    {"word": "i", "weight": 0, "decomp": [
+                        {"pattern": "* i @be *", "reasmb": []},
+                        {"pattern": "* i @be *", "reasmb": []},
+                        {"pattern": "*", "reasmb": []},
+                        {"pattern": "* i *", "reasmb": []}
+                    ]},
+                    {"word": "i", "weight": 1, "decomp": [{"pattern": "*", "reasmb": []}]}
+                ]
+            }"#,
+        );
+
+        let unreachable = |pattern: &str| ValidationWarning::UnreachableDecomposition {
+            word: "i".to_string(),
+            pattern: pattern.to_string(),
+        };
+
+        let expected = vec![
+            ValidationWarning::UppercaseKeyword {
+                word: "Hello".to_string(),
+            },
+            ValidationWarning::NoDecomposition {
+                word: "Hello".to_string(),
+            },
+            unreachable("* i @be *"),
+            unreachable("* i *"),
+            ValidationWarning::DuplicateKeyword {
+                word: "i".to_string(),
+            },
+            ValidationWarning::UnusedSynonym {
+                label: "@happy".to_string(),
+            },
+        ];
+
+        assert_eq!(expected, rules.warnings());
+    }
+
+    #[test]
+    fn test_validate_empty() {
+        let expected = vec![
+            ValidationError::NoInitial,
+            ValidationError::NoFinal,
+            ValidationError::NoXnone,
+        ];
+
+        assert_eq!(Err(expected), parse("{}").validate());
+    }
+
+    #[test]
+    fn test_validate_decompositions() {
+        let rules = parse(
+            r#"{
+                "initial": ["hi"],
+                "final": ["bye"],
+                "synon": [{"label": "@be", "list": ["am"]}],
+                "key": [
+                    {"word": "xnone", "weight": 0, "decomp": [{"pattern": "*", "reasmb": ["(1)"]}]},
+                    {"word": "i", "weight": 0, "decomp": [
+                        {"pattern": "* i @be *", "reasmb": ["(3) ?", "(4) ?"]},
+                        {"pattern": "* i @feel *", "reasmb": []}
+                    ]}
+                ]
+            }"#,
+        );
+
+        let expected = vec![
+            ValidationError::InvalidGroup {
+                word: "i".to_string(),
+                pattern: "* i @be *".to_string(),
+                template: "(4) ?".to_string(),
+            },
+            ValidationError::EmptyReasmb {
+                word: "i".to_string(),
+                pattern: "* i @feel *".to_string(),
+            },
+            ValidationError::UnknownSynonym {
+                word: "i".to_string(),
+                pattern: "* i @feel *".to_string(),
+                label: "@feel".to_string(),
+            },
+        ];
+
+        assert_eq!(Err(expected), rules.validate());
+    }
+
+    #[test]
+    fn test_validate_parenthesis_without_group() {
+        for template in &["(", "(é)", "(a)"] {
+            let mut rules = parse(
+                r#"{
+                    "initial": ["hi"],
+                    "final": ["bye"],
+                    "key": [{"word": "xnone", "weight": 0, "decomp": [{"pattern": "*", "reasmb": []}]}]
+                }"#,
+            );
+            rules.keywords[0].decomposition[0].reasmb = vec![format!("well {} then", template)];
+
+            let expected = vec![ValidationError::InvalidGroup {
+                word: "xnone".to_string(),
+                pattern: "*".to_string(),
+                template: format!("well {} then", template),
+            }];
+
+            assert_eq!(Err(expected), rules.validate());
+        }
+    }
+}
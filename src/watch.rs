@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Polls the modification time of a file, or of the files of a directory, in
+/// a background thread, and calls `on_change` each time it changes.
+pub fn watch<F>(path: PathBuf, interval: Duration, on_change: F) -> thread::JoinHandle<()>
+where
+    F: Fn() + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut last_modified = modified(&path);
+
+        loop {
+            thread::sleep(interval);
+
+            let current = modified(&path);
+
+            if current != last_modified {
+                last_modified = current;
+                on_change();
+            }
+        }
+    })
+}
+
+/// Returns the latest modification time of a file, or of a directory and its
+/// files.
+fn modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::metadata(path).ok()?;
+    let mut modified = metadata.modified().ok()?;
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).ok()?.flatten() {
+            if let Ok(time) = entry.metadata().and_then(|m| m.modified()) {
+                modified = modified.max(time);
+            }
+        }
+    }
+
+    Some(modified)
+}